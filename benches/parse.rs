@@ -0,0 +1,38 @@
+//!
+//! Benchmarks for the POSIX parser
+//!
+//! Run with `cargo bench` before and after a change to compare, and with `cargo bench --features simd` to compare the
+//! SIMD special-character scan.
+//!
+#[macro_use]
+extern crate criterion;
+extern crate cmdline_words_parser;
+
+use criterion::{Criterion, Throughput, BatchSize};
+
+fn bench(c: &mut Criterion, name: &str, input: &str) {
+	let mut group = c.benchmark_group("parse_posix");
+	group.throughput(Throughput::Bytes(input.len() as u64));
+	group.bench_function(name, |b| b.iter_batched_ref(
+		|| String::from(input),
+		|buf| ::cmdline_words_parser::parse_posix(buf).count(),
+		BatchSize::SmallInput,
+		));
+	group.finish();
+}
+
+fn short_inputs(c: &mut Criterion) {
+	bench(c, "plain", "gcc -O2 -Wall -o output main.c util.c parser.c lexer.c -lm -lpthread");
+	bench(c, "escaped", r"cp some\ file\ name another\ file\ name /tmp/target\ dir/");
+	bench(c, "quoted", r#"echo "double quoted \"string\"" 'single quoted' "mixed"'quotes'"#);
+	bench(c, "long", &"word ".repeat(1000));
+}
+
+fn huge_input(c: &mut Criterion) {
+	// Megabyte-scale, with long runs of plain bytes between quoting
+	let huge = "--some-long-option-name=/usr/local/share/some/kind/of/path/to/a/file.txt 'single quoted' ".repeat(12_000);
+	bench(c, "huge", &huge);
+}
+
+criterion_group!(benches, short_inputs, huge_input);
+criterion_main!(benches);
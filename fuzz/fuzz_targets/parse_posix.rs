@@ -0,0 +1,30 @@
+//!
+//! Fuzz target: Arbitrary bytes into `parse_posix` (byte-slice mode)
+//!
+//! Run with `cargo fuzz run parse_posix`. Checks that parsing never panics, and that the total output length never
+//! exceeds the input length (as required for in-place parsing).
+//!
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cmdline_words_parser;
+
+use cmdline_words_parser::PosixParser;
+
+fuzz_target!(|data: &[u8]| {
+	let check = |opts: PosixParser| {
+		let mut buf = data.to_vec();
+		let total: usize = opts.parse(&mut buf[..]).map(|w| w.len()).sum();
+		assert!(total <= data.len());
+	};
+	check(PosixParser::new());
+	check(PosixParser::new().shell_accurate_escapes(true).keep_enclosing_quotes(true));
+	check(PosixParser::new().unicode_whitespace(true).map_smart_quotes(true).max_token_len(8));
+
+	// And via `str` (which has the additional UTF-8 consistency check)
+	if let Ok(s) = ::std::str::from_utf8(data) {
+		let mut s = s.to_owned();
+		let total: usize = ::cmdline_words_parser::parse_posix(&mut s).map(|w| w.len()).sum();
+		assert!(total <= data.len());
+	}
+});
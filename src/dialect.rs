@@ -0,0 +1,130 @@
+//!
+//! Generic word splitting, driven by a pluggable [Dialect]
+//!
+use crate::split_off_front_inplace_mut;
+use crate::ByteStringSlice;
+
+/// Result of passing a byte through a [Dialect]'s state machine
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
+pub enum Action
+{
+	/// Emit the given byte into the word (replacing the input byte), must be ASCII
+	Emit(u8),
+	/// Emit the input byte unchanged
+	Continue,
+	/// Drop the input byte (e.g. a quote or escape character)
+	Skip,
+	/// End the word before this byte, consuming it (along with any following separators)
+	Break,
+}
+
+/// A set of escaping rules
+///
+/// Implementations only have to provide the per-byte state machine, [DialectWords] handles the in-place unescaping
+/// and word splitting. To keep UTF-8 valid, non-ASCII bytes are always part of the word (they're passed to
+/// [Dialect::step] so the state can change, but the returned action is ignored), and dialects can only emit ASCII.
+pub trait Dialect
+{
+	/// State carried between bytes of a word (reset to `Default` at the start of each word)
+	type State: Copy + Default;
+
+	/// Process a single byte
+	///
+	/// [DialectWords] panics if this returns a non-ASCII [Action::Emit]. For non-ASCII input bytes, only the new state
+	/// is used (the byte is always kept unchanged).
+	fn step(&self, state: Self::State, byte: u8) -> (Self::State, Action);
+
+	/// Check if a (ASCII) byte is a separator to be consumed between words
+	fn is_separator(&self, byte: u8) -> bool;
+}
+
+/// Iterator yeilding unescaped words using the rules of a [Dialect]
+pub struct DialectWords<'a, D, T: ?Sized + ByteStringSlice>
+{
+	buf: &'a mut [u8],
+	dialect: D,
+	_pd: ::std::marker::PhantomData<T>,
+}
+impl<'a, D: Dialect, T: ?Sized + ByteStringSlice> DialectWords<'a, D, T>
+{
+	pub(crate) fn new(input_bytes: &mut [u8], dialect: D) -> DialectWords<'_, D, T> {
+		DialectWords {
+			buf: input_bytes,
+			dialect,
+			_pd: ::std::marker::PhantomData::<T>,
+			}
+	}
+
+	/// Get the remaining (unparsed) bytes
+	pub(crate) fn remaining(&self) -> &[u8] {
+		self.buf
+	}
+
+	/// Consume (and clear) any leading separators
+	pub(crate) fn skip_separators(&mut self) {
+		let mut start = 0;
+		while start < self.buf.len() && self.is_separator(self.buf[start]) {
+			self.buf[start] = 0;
+			start += 1;
+		}
+		split_off_front_inplace_mut(&mut self.buf, start);
+	}
+
+	fn is_separator(&self, byte: u8) -> bool {
+		byte < 0x80 && self.dialect.is_separator(byte)
+	}
+}
+impl<'a, D: Dialect, T: ?Sized + ByteStringSlice + 'a> Iterator for DialectWords<'a, D, T>
+{
+	type Item = &'a T;
+	fn next(&mut self) -> Option<&'a T> {
+		// 1. Skip leading separators, then check for an empty string (this means the end has been reached).
+		self.skip_separators();
+		if self.buf.is_empty() {
+			return None;
+		}
+
+		// 2. Iterate byte-wise along the string, passing each byte through the dialect
+		let mut state = D::State::default();
+		let mut outpos = 0;
+		let mut endpos = self.buf.len();
+		for i in 0 .. self.buf.len()
+		{
+			let byte = self.buf[i];
+			// Non-ASCII bytes only update the state, so multi-byte characters are always copied intact (but still end an
+			// escape, for example)
+			let (new_state, action) = self.dialect.step(state, byte);
+			state = new_state;
+			let action = if byte < 0x80 { action } else { Action::Continue };
+			let out = match action
+				{
+				Action::Emit(b) => {
+					assert!(b < 0x80, "Dialect emitted a non-ASCII byte ({:#x})", b);
+					b
+					},
+				Action::Continue => byte,
+				Action::Skip => continue,
+				Action::Break => {
+					self.buf[i] = 0;
+					endpos = i + 1;
+					break;
+					},
+				};
+			if outpos != i {
+				assert!(outpos < i);
+				self.buf[i] = 0;	// DEFENSIVE. Mangle string at read position to ensure no strays
+			}
+			self.buf[outpos] = out;
+			outpos += 1;
+		}
+
+		// 3. Consume multiple separators
+		while endpos < self.buf.len() && self.is_separator(self.buf[endpos]) {
+			self.buf[endpos] = 0;
+			endpos += 1;
+		}
+
+		let ret = &split_off_front_inplace_mut(&mut self.buf, endpos)[..outpos];
+		Some( T::from_bytes(ret).expect("Dialect Word spliting caused UTF-8 inconsistency") )
+	}
+}
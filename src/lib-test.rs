@@ -0,0 +1,195 @@
+//!
+//! Tests for shared helpers
+//!
+use crate::split_off_front_inplace_mut;
+#[cfg(not(feature="std"))]
+use alloc::{string::String, vec::Vec};
+
+#[test]
+fn split_off_zero()
+{
+	let mut data = [1, 2, 3];
+	let mut slice = &mut data[..];
+	assert_eq!(split_off_front_inplace_mut(&mut slice, 0), &mut []);
+	assert_eq!(slice, &mut [1, 2, 3]);
+}
+
+#[test]
+fn split_off_full()
+{
+	let mut data = [1, 2, 3];
+	let mut slice = &mut data[..];
+	assert_eq!(split_off_front_inplace_mut(&mut slice, 3), &mut [1, 2, 3]);
+	assert!(slice.is_empty());
+	// And again on the now-empty slice
+	assert!(split_off_front_inplace_mut(&mut slice, 0).is_empty());
+	assert!(slice.is_empty());
+}
+
+#[test]
+fn split_off_middle()
+{
+	let mut data = [1, 2, 3];
+	let mut slice = &mut data[..];
+	assert_eq!(split_off_front_inplace_mut(&mut slice, 1), &mut [1]);
+	assert_eq!(slice, &mut [2, 3]);
+}
+
+#[test]
+#[should_panic]
+#[cfg(debug_assertions)]
+fn split_off_past_end()
+{
+	let mut data = [1, 2, 3];
+	let mut slice = &mut data[..];
+	split_off_front_inplace_mut(&mut slice, 4);
+}
+
+#[test]
+fn whitespace_only()
+{
+	let mut s = String::from("  a\\b \"c\"\t'd'\n");
+	let mut iter = crate::parse_whitespace(&mut s);
+	assert_eq!(iter.next(), Some("a\\b"));
+	assert_eq!(iter.next(), Some("\"c\""));
+	assert_eq!(iter.next(), Some("'d'"));
+	assert_eq!(iter.next(), None);
+	assert_eq!(s, "  a\\b \"c\"\t'd'\n");
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn posix_preserving()
+{
+	let s = String::from("a\\ b 'c d'");
+	let mut iter = crate::parse_posix_preserving(&s);
+	assert_eq!(iter.next().as_deref(), Some("a b"));
+	assert_eq!(iter.next().as_deref(), Some("c d"));
+	assert_eq!(iter.next(), None);
+	assert_eq!(s, "a\\ b 'c d'");
+}
+
+#[test]
+fn mut_ref_inputs()
+{
+	fn first_word<T: ?Sized + crate::ByteString>(s: &mut T) -> Option<&T::OutSlice> {
+		crate::parse_posix(s).next()
+	}
+	let mut owned = String::from("a b");
+	let s: &mut str = &mut owned;
+	assert_eq!(crate::parse_posix(s).next(), Some("a"));
+
+	let mut owned = String::from("c d");
+	let mut s: &mut str = &mut owned;
+	assert_eq!(crate::parse_posix(&mut s).next(), Some("c"));
+
+	let mut bytes = *b"e f";
+	let mut b: &mut [u8] = &mut bytes;
+	assert_eq!(first_word(&mut b), Some(&b"e"[..]));
+
+	let mut owned = String::from("g h");
+	let mut s = &mut owned;
+	assert_eq!(first_word(&mut s), Some("g"));
+}
+
+#[test]
+#[cfg(all(feature="std", unix))]
+fn path_words()
+{
+	use std::path::{Path, PathBuf};
+	let mut cmdline = PathBuf::from("cp /a/b '/c/d e'");
+	let words: Vec<&Path> = crate::parse_posix(&mut cmdline).collect();
+	assert_eq!(words, [Path::new("cp"), Path::new("/a/b"), Path::new("/c/d e")]);
+	assert_eq!(words[1].file_name(), Some("b".as_ref()));
+}
+
+#[test]
+#[cfg(all(feature="std", unix))]
+fn osstr_non_utf8()
+{
+	use std::ffi::{OsStr, OsString};
+	use std::os::unix::ffi::{OsStrExt, OsStringExt};
+	let mut cmdline = OsString::from_vec(b"cp caf\xE9.txt '\xFF\xFE dir'/ \\\xFF\\ x".to_vec());
+	let words: Vec<&OsStr> = crate::parse_posix(&mut *cmdline).collect();
+	assert_eq!(words, [OsStr::new("cp"), OsStr::from_bytes(b"caf\xE9.txt"), OsStr::from_bytes(b"\xFF\xFE dir/"), OsStr::from_bytes(b"\xFF x")]);
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn posix_nonempty()
+{
+	use crate::posix::ParseError;
+	assert_eq!(crate::try_parse_posix_nonempty(&mut String::new()), Err(ParseError::EmptyInput));
+	assert_eq!(crate::try_parse_posix_nonempty(&mut String::from(" \t\n ")), Err(ParseError::EmptyInput));
+	assert_eq!(crate::try_parse_posix_nonempty(&mut String::from("rm 'a b'")).unwrap(), ["rm", "a b"]);
+	// An empty quoted word is still a word
+	assert_eq!(crate::try_parse_posix_nonempty(&mut String::from("''")).unwrap(), [""]);
+	// Other errors are reported as-is
+	assert_eq!(crate::try_parse_posix_nonempty(&mut String::from("echo 'a")), Err(ParseError::UnterminatedQuote));
+}
+
+#[test]
+fn posix_prefix()
+{
+	let mut buf = [b'x'; 32];
+	let line = b"cmd 'a b' c\\";
+	buf[..line.len()].copy_from_slice(line);
+	let mut words = crate::parse_posix_prefix(&mut buf, line.len());
+	assert_eq!(words.next(), Some(&b"cmd"[..]));
+	assert_eq!(words.next(), Some(&b"a b"[..]));
+	// The trailing backslash isn't joined with the bytes after `len`
+	assert_eq!(words.next(), Some(&b"c"[..]));
+	assert_eq!(words.next(), None);
+	assert!(buf[line.len()..].iter().all(|&b| b == b'x'));
+}
+
+/// Dialect for testing the driver: `A` is replaced with `B`, `-` is dropped, `,` ends a word, `!` emits 0xFF, and non-ASCII is
+/// dropped (which the driver ignores)
+struct TestDialect;
+impl crate::dialect::Dialect for TestDialect
+{
+	type State = ();
+	fn step(&self, _: (), byte: u8) -> ((), crate::dialect::Action) {
+		use crate::dialect::Action;
+		((), match byte
+			{
+			b'A' => Action::Emit(b'B'),
+			b'-' => Action::Skip,
+			b',' => Action::Break,
+			b' ' => Action::Break,
+			b'!' => Action::Emit(0xFF),
+			// Would split multi-byte characters, if it were used
+			0x80 ..= 0xFF => Action::Skip,
+			_ => Action::Continue,
+			})
+	}
+	fn is_separator(&self, byte: u8) -> bool {
+		byte == b' '
+	}
+}
+
+#[test]
+fn dialect_driver()
+{
+	// Emitted bytes are written even when nothing earlier in the word was skipped
+	let mut s = String::from("A xA -A");
+	assert_eq!(crate::parse(TestDialect, &mut s).collect::<Vec<_>>(), ["B", "xB", "B"]);
+	// A break on a non-separator consumes it
+	let mut s = String::from("a,b,,c ,d");
+	assert_eq!(crate::parse(TestDialect, &mut s).collect::<Vec<_>>(), ["a", "b", "", "c", "", "d"]);
+	// The action for non-ASCII is ignored
+	let mut s = String::from(",A\u{C2C}");
+	assert_eq!(crate::parse(TestDialect, &mut s).collect::<Vec<_>>(), ["", "B\u{C2C}"]);
+}
+
+#[test]
+#[cfg(feature="std")]
+fn dialect_non_ascii_emit()
+{
+	let mut s = String::from("-a!b");
+	let r = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| crate::parse(TestDialect, &mut s).count()));
+	assert!(r.is_err());
+	// The panic happens before the byte is written, so the string is still valid
+	assert!(::std::str::from_utf8(s.as_bytes()).is_ok());
+	assert!(s.contains('b'));
+}
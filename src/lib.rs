@@ -2,28 +2,67 @@
 //! than the original).
 //!
 //! - [parse_posix] Parses strings using unix shell escaping rules (see [PosixShellWords] for exact rules)
+//! - [PosixParser] Configurable version of [parse_posix]
+//! - [parse_whitespace] Splits strings on whitespace only (no escaping)
+//! - [parse_win32] Parses strings using cmd.exe escaping rules (see [Win32ShellWords])
+//! - [parse] Parses strings using a custom set of rules (see [dialect::Dialect])
+//! - [posix::quote] (and friends) Quotes words such that they parse back to the original
 //!
 //! Note: This crate has no way of handling variable substitions.
+//!
+//! Note: Parsing is destructive - the source string is unescaped in-place, and is left with unspecified (but valid)
+//! contents afterwards. Use [parse_posix_preserving] if the source needs to be kept intact.
 #![crate_type="lib"]
 #![crate_name="cmdline_words_parser"]
 #![cfg_attr(not(feature="std"), no_std)]
 #[cfg(feature="alloc")]
 extern crate alloc;
+#[cfg(feature="futures")]
+extern crate futures_core;
 
 #[cfg(not(feature="std"))]
 mod std {
+	#[cfg(feature="simd")]
+	pub use core::arch;
+	pub use core::cmp;
+	pub use core::fmt;
 	pub use core::marker;
 	pub use core::mem;
+	pub use core::ops;
+	#[cfg(feature="alloc")]
+	pub use core::pin;
 	pub use core::str;
+	#[cfg(feature="alloc")]
+	pub use core::task;
 }
 
+use std::fmt;
+
+#[cfg(all(test, feature="proptest-tests"))]
+#[macro_use]
+extern crate proptest;
+#[cfg(all(test, feature="proptest-tests"))]
+extern crate shlex;
+#[cfg(test)]
+extern crate futures;
+
+#[cfg(test)]
+#[path="lib-test.rs"]
+mod test;
+
 pub use crate::posix::PosixShellWords;
-mod posix;
-//pub use crate::win32::Win32ShellWords;
-//mod win32;
+pub use crate::posix::PosixParser;
+pub mod posix;
+pub use crate::win32::Win32ShellWords;
+pub mod win32;
+pub use crate::whitespace::WhitespaceWords;
+mod whitespace;
+pub mod dialect;
 
 /// Parse string in a UNIX/POSIX-like manner
 ///
+/// The string is modified in-place, see [parse_posix_preserving] for a non-destructive version.
+///
 /// ```
 /// let mut cmdline = String::from(r"Hello\ World 'Second Argument'");
 /// let mut parse = ::cmdline_words_parser::parse_posix(&mut cmdline);
@@ -31,14 +70,134 @@ mod posix;
 /// assert_eq!( parse.next(), Some("Second Argument") );
 /// assert_eq!( parse.next(), None );
 /// ```
-pub fn parse_posix<T: ?Sized + ByteString>(string: &mut T) -> PosixShellWords<T::OutSlice> {
-	// SAFE: Should be ensuring correct (visible) UTF-8
-	PosixShellWords::new(unsafe { string.as_mut_bytes() })
+pub fn parse_posix<T: ?Sized + ByteString>(string: &mut T) -> PosixShellWords<'_, T::OutSlice> {
+	PosixParser::new().parse(string)
+}
+/// Parse the first `len` bytes of a buffer in a UNIX/POSIX-like manner (e.g. a line read into a fixed-size array)
+///
+/// Panics if `len` is larger than the buffer.
+///
+/// ```
+/// let mut buf = [0xFF; 16];
+/// buf[..5].copy_from_slice(b"ls -l");
+/// let mut parse = ::cmdline_words_parser::parse_posix_prefix(&mut buf, 5);
+/// assert_eq!( parse.next(), Some(&b"ls"[..]) );
+/// assert_eq!( parse.next(), Some(&b"-l"[..]) );
+/// assert_eq!( parse.next(), None );
+/// ```
+pub fn parse_posix_prefix(buf: &mut [u8], len: usize) -> PosixShellWords<'_, [u8]> {
+	parse_posix(&mut buf[..len])
+}
+/// Parse a string in a UNIX/POSIX-like manner, with `is_sep` deciding which bytes are separators
+///
+/// See [PosixParser::separator_classifier].
+///
+/// ```
+/// let mut cmdline = String::from("a1b22'c3d'");
+/// let words: Vec<_> = ::cmdline_words_parser::parse_posix_with_classifier(&mut cmdline, |b| b.is_ascii_digit()).collect();
+/// assert_eq!( words, ["a", "b", "c3d"] );
+/// // Closures can capture their environment
+/// let sep = b',';
+/// let mut cmdline = String::from("a,b c");
+/// let words: Vec<_> = ::cmdline_words_parser::parse_posix_with_classifier(&mut cmdline, |b| b == sep).collect();
+/// assert_eq!( words, ["a", "b c"] );
+/// ```
+pub fn parse_posix_with_classifier<T: ?Sized + ByteString, F: Fn(u8) -> bool + Copy>(string: &mut T, is_sep: F) -> PosixShellWords<'_, T::OutSlice, F> {
+	PosixParser::new().separator_classifier(is_sep).parse(string)
+}
+/// Parse a string in a UNIX/POSIX-like manner, without modifying it
+///
+/// The string is copied into a scratch buffer before parsing, and the words are returned as owned strings.
+///
+/// ```
+/// let cmdline = String::from(r"Hello\ World 'Second Argument'");
+/// let words: Vec<_> = ::cmdline_words_parser::parse_posix_preserving(&cmdline).collect();
+/// assert_eq!( words, ["Hello World", "Second Argument"] );
+/// assert_eq!( cmdline, r"Hello\ World 'Second Argument'" );
+/// ```
+#[cfg(feature="alloc")]
+pub fn parse_posix_preserving(string: &str) -> ::alloc::vec::IntoIter<::alloc::string::String> {
+	let mut scratch = ::alloc::string::String::from(string);
+	parse_posix(&mut scratch).to_vec().into_iter()
+}
+/// Parse a string in a UNIX/POSIX-like manner, returning an error if it's malformed or has no words
+///
+/// Unterminated quotes/escapes are errors (see [posix::Unterminated::Error]), and whitespace-only input is empty.
+///
+/// ```
+/// use cmdline_words_parser::{try_parse_posix_nonempty, posix::ParseError};
+/// assert_eq!( try_parse_posix_nonempty(&mut String::from("ls -l")), Ok(vec!["ls".to_owned(), "-l".to_owned()]) );
+/// assert_eq!( try_parse_posix_nonempty(&mut String::from("  ")), Err(ParseError::EmptyInput) );
+/// ```
+#[cfg(feature="alloc")]
+pub fn try_parse_posix_nonempty(string: &mut str) -> Result<::alloc::vec::Vec<::alloc::string::String>, posix::ParseError> {
+	let words = PosixParser::new().on_unterminated(posix::Unterminated::Error).parse(string).collect_result()?;
+	if words.is_empty() {
+		return Err(posix::ParseError::EmptyInput);
+	}
+	Ok(words)
+}
+/// Parse a string in a UNIX/POSIX-like manner, recording the parser's decisions into `trace` (for debugging)
+///
+/// The trace is recorded as the words are parsed, and doesn't change the result. See [PosixParser::parse_traced] to use
+/// other options.
+///
+/// ```
+/// use cmdline_words_parser::posix::TraceEvent;
+/// let mut cmdline = String::from(r"a\ b");
+/// let mut trace = Vec::new();
+/// let words: Vec<_> = ::cmdline_words_parser::parse_posix_traced(&mut cmdline, &mut trace).collect();
+/// assert_eq!( words, ["a b"] );
+/// assert_eq!( trace, [
+///     TraceEvent::Escape { pos: 2, input: b' ', output: Some(b' ') },
+///     TraceEvent::TokenBoundary { pos: 4 },
+///     ] );
+/// ```
+#[cfg(feature="alloc")]
+pub fn parse_posix_traced<'a, T: ?Sized + ByteString>(string: &'a mut T, trace: &'a mut ::alloc::vec::Vec<posix::TraceEvent>) -> PosixShellWords<'a, T::OutSlice> {
+	PosixParser::new().parse_traced(string, trace)
+}
+/// Parse a string using the rules of an arbitrary [dialect::Dialect]
+///
+/// ```
+/// use cmdline_words_parser::{parse, posix::PosixDialect, win32::Win32Dialect};
+/// let mut cmdline = String::from(r"Hello\ World ^'Second Argument'");
+/// assert_eq!( parse(PosixDialect, &mut cmdline.clone()).collect::<Vec<_>>(), ["Hello World", "^Second Argument"] );
+/// assert_eq!( parse(Win32Dialect, &mut cmdline).collect::<Vec<_>>(), [r"Hello\", "World", "'Second", "Argument'"] );
+/// ```
+pub fn parse<D: dialect::Dialect, T: ?Sized + ByteString>(dialect: D, string: &mut T) -> dialect::DialectWords<'_, D, T::OutSlice> {
+	// SAFE: Only ASCII bytes are replaced/removed, and writes are checked to be ASCII before they happen
+	dialect::DialectWords::new(unsafe { string.as_mut_bytes() }, dialect)
+}
+/// Split a string on whitespace only, with no escape or quote processing
+///
+/// A fast path for input known to contain no quoting (quotes and backslashes are returned literally)
+///
+/// ```
+/// let mut cmdline = String::from(r#"a\b "c""#);
+/// let mut parse = ::cmdline_words_parser::parse_whitespace(&mut cmdline);
+/// assert_eq!( parse.next(), Some(r"a\b") );
+/// assert_eq!( parse.next(), Some(r#""c""#) );
+/// assert_eq!( parse.next(), None );
+/// ```
+pub fn parse_whitespace<T: ?Sized + ByteString>(string: &mut T) -> WhitespaceWords<'_, T::OutSlice> {
+	// SAFE: The string is never modified
+	WhitespaceWords::new(unsafe { string.as_mut_bytes() })
+}
+/// Parse a string using cmd.exe/win32 escaping rules
+///
+/// ```
+/// let mut cmdline = String::from(r#"dir "C:\Program Files" /s"#);
+/// let mut parse = ::cmdline_words_parser::parse_win32(&mut cmdline);
+/// assert_eq!( parse.next(), Some("dir") );
+/// assert_eq!( parse.next(), Some(r"C:\Program Files") );
+/// assert_eq!( parse.next(), Some("/s") );
+/// assert_eq!( parse.next(), None );
+/// ```
+pub fn parse_win32<T: ?Sized + ByteString>(string: &mut T) -> Win32ShellWords<'_, T::OutSlice> {
+	// SAFE: Only ASCII positions are modified
+	Win32ShellWords::new(unsafe { string.as_mut_bytes() })
 }
-///// Parse a string using cmd.exe/win32 escaping rules
-//pub fn parse_win32<T: ?Sized + StrExt>(string: &mut T) -> Win32ShellWords<T::OutSlice> {
-//	Win32ShellWords::new(unsafe { string.as_mut_bytes() })
-//}
 
 /// Trait representing types that can be in-place parsed (i.e. ASCII-compatible byte strings)
 pub trait ByteString
@@ -47,6 +206,9 @@ pub trait ByteString
 	type OutSlice: ?Sized + ByteStringSlice;
 
 	/// Get the string as a mutable sequence of bytes
+	///
+	/// # Safety
+	/// The caller must only modify the bytes in a way that keeps the original type's invariants (e.g. UTF-8 for `str`)
 	unsafe fn as_mut_bytes(&mut self) -> &mut [u8];
 
 	// TODO: Maybe use this instead of the extension trait?
@@ -94,7 +256,32 @@ impl ByteString for ::alloc::string::String {
 	type OutSlice = str;
 	#[doc(hidden)]
 	unsafe fn as_mut_bytes(&mut self) -> &mut [u8] {
-		&mut **self.as_mut_vec()
+		self.as_mut_vec()
+	}
+}
+/// Paths (only on unix, where paths are arbitrary bytes), parsing to `&Path` words
+#[cfg(all(feature="std", unix))]
+impl ByteString for ::std::path::Path {
+	type OutSlice = ::std::path::Path;
+	#[doc(hidden)]
+	unsafe fn as_mut_bytes(&mut self) -> &mut [u8] {
+		self.as_mut_os_str().as_mut_bytes()
+	}
+}
+#[cfg(all(feature="std", unix))]
+impl ByteString for ::std::path::PathBuf {
+	type OutSlice = ::std::path::Path;
+	#[doc(hidden)]
+	unsafe fn as_mut_bytes(&mut self) -> &mut [u8] {
+		(**self).as_mut_bytes()
+	}
+}
+/// Allows passing an existing mutable reference (e.g. `&mut &mut str`) without reborrowing as `&mut *s`
+impl<T: ?Sized + ByteString> ByteString for &mut T {
+	type OutSlice = T::OutSlice;
+	#[doc(hidden)]
+	unsafe fn as_mut_bytes(&mut self) -> &mut [u8] {
+		(**self).as_mut_bytes()
 	}
 }
 
@@ -103,28 +290,84 @@ impl ByteString for ::alloc::string::String {
 #[doc(hidden)]
 pub trait ByteStringSlice {
 	fn from_bytes(bytes: &[u8]) -> Option<&Self>;
+	fn from_bytes_mut(bytes: &mut [u8]) -> Option<&mut Self>;
+	/// Write to a formatter, replacing invalid UTF-8 with U+FFFD
+	fn write_lossy(&self, out: &mut dyn fmt::Write) -> fmt::Result;
 }
 impl ByteStringSlice for str {
 	fn from_bytes(bytes: &[u8]) -> Option<&Self> {
 		::std::str::from_utf8(bytes).ok()
 	}
+	fn from_bytes_mut(bytes: &mut [u8]) -> Option<&mut Self> {
+		::std::str::from_utf8_mut(bytes).ok()
+	}
+	fn write_lossy(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+		out.write_str(self)
+	}
 }
 impl ByteStringSlice for [u8] {
 	fn from_bytes(bytes: &[u8]) -> Option<&Self> {
 		Some(bytes)
 	}
+	fn from_bytes_mut(bytes: &mut [u8]) -> Option<&mut Self> {
+		Some(bytes)
+	}
+	fn write_lossy(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+		let mut rest = self;
+		loop {
+			match ::std::str::from_utf8(rest)
+			{
+			Ok(s) => return out.write_str(s),
+			Err(e) => {
+				let (valid, invalid) = rest.split_at(e.valid_up_to());
+				// SAFE: Checked by `from_utf8`
+				out.write_str(unsafe { ::std::str::from_utf8_unchecked(valid) })?;
+				out.write_char('\u{FFFD}')?;
+				// `None` means the input ended part-way through a character
+				rest = &invalid[e.error_len().unwrap_or(invalid.len())..];
+				},
+			}
+		}
+	}
 }
 #[cfg(feature="std")]
 impl ByteStringSlice for ::std::ffi::OsStr {
 	fn from_bytes(bytes: &[u8]) -> Option<&Self> {
 		// SAFE: OsStr is bytes, and string is only modified on ASCII characters
-		Some( unsafe { ::std::mem::transmute(bytes) } )
+		Some( unsafe { ::std::mem::transmute::<&[u8], &::std::ffi::OsStr>(bytes) } )
+	}
+	fn from_bytes_mut(bytes: &mut [u8]) -> Option<&mut Self> {
+		// SAFE: Same as above
+		Some( unsafe { ::std::mem::transmute::<&mut [u8], &mut ::std::ffi::OsStr>(bytes) } )
+	}
+	fn write_lossy(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+		write!(out, "{}", self.to_string_lossy())
+	}
+}
+
+#[cfg(all(feature="std", unix))]
+impl ByteStringSlice for ::std::path::Path {
+	fn from_bytes(bytes: &[u8]) -> Option<&Self> {
+		Some( ::std::path::Path::new(<::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>::from_bytes(bytes)) )
+	}
+	fn from_bytes_mut(bytes: &mut [u8]) -> Option<&mut Self> {
+		let s = ::std::ffi::OsStr::from_bytes_mut(bytes)?;
+		// SAFE: Path is a transparent wrapper around OsStr (which is how `Path::new` is implemented)
+		Some( unsafe { &mut *(s as *mut ::std::ffi::OsStr as *mut ::std::path::Path) } )
+	}
+	fn write_lossy(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+		write!(out, "{}", self.display())
 	}
 }
 
 /// Helper: Splits the front off a mutable slice
+///
+/// `idx` may be anywhere from zero (returns an empty front, leaving `slice` unchanged) up to `slice.len()` (returns
+/// the entire slice, leaving `slice` empty)
+#[inline]
 fn split_off_front_inplace_mut<'a, T>(slice: &mut &'a mut [T], idx: usize) -> &'a mut [T] {
-	let (ret, tail) = ::std::mem::replace(slice, &mut []).split_at_mut(idx);
+	debug_assert!(idx <= slice.len(), "split_off_front_inplace_mut: {} > {}", idx, slice.len());
+	let (ret, tail) = ::std::mem::take(slice).split_at_mut(idx);
 	*slice = tail;
 	ret
 }
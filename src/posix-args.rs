@@ -0,0 +1,110 @@
+//!
+//! Structuring of words into command-line arguments (flags, `--key=value` pairs, and positionals)
+//!
+use super::PosixShellWords;
+use crate::{ByteString, ByteStringSlice};
+
+/// A command-line argument, see [parse_args]
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
+pub enum Arg<'a, T: ?Sized + 'a>
+{
+	/// `--name` or `-n` (without the leading dashes)
+	Flag(&'a T),
+	/// `--name=value`, or `--name value` / `-n value` if `name` is one of the [Args::value_keys]
+	KeyValue(&'a T, &'a T),
+	/// Anything else, including quoted/escaped words that start with `-`, `-` itself, and everything after `--`
+	Positional(&'a T),
+}
+
+/// Iterator over arguments, see [parse_args]
+pub struct Args<'a, T: ?Sized + ByteStringSlice>
+{
+	words: PosixShellWords<'a, T>,
+	only_positional: bool,
+	value_keys: &'a [&'a str],
+}
+impl<'a, T: ?Sized + ByteStringSlice + 'a> Args<'a, T>
+{
+	/// Flag names (without the leading dashes) that take the next word as their value
+	///
+	/// With these set, `--key value` and `-k value` give [Arg::KeyValue] (the value is taken as-is, even if it starts
+	/// with `-`). A value flag at the end of the input is still an [Arg::Flag].
+	///
+	/// ```
+	/// use cmdline_words_parser::posix::{parse_args, Arg};
+	/// let mut cmdline = String::from("--out dir -o -x --out=a -v");
+	/// let args: Vec<_> = parse_args(&mut cmdline).value_keys(&["out", "o"]).collect();
+	/// assert_eq!( args, [Arg::KeyValue("out", "dir"), Arg::KeyValue("o", "-x"), Arg::KeyValue("out", "a"), Arg::Flag("v")] );
+	/// ```
+	pub fn value_keys(mut self, keys: &'a [&'a str]) -> Self {
+		self.value_keys = keys;
+		self
+	}
+
+	/// Take the next word as-is (e.g. as the value for the preceding `--key value` flag)
+	pub fn value(&mut self) -> Option<&'a T> {
+		self.words.next()
+	}
+}
+impl<'a, T: ?Sized + ByteStringSlice + 'a> Iterator for Args<'a, T>
+{
+	type Item = Arg<'a, T>;
+	fn next(&mut self) -> Option<Arg<'a, T>> {
+		let from_bytes = |b: &'a [u8]| T::from_bytes(b).expect("Argument split caused UTF-8 inconsistency");
+		loop {
+			// Only an unquoted and unescaped leading `-` makes a flag
+			let start = self.words.opts.separators_len(self.words.buf);
+			let is_flag = !self.only_positional && self.words.buf.get(start) == Some(&b'-');
+			let w = self.words.next_word()?;
+			let bytes = w.bytes;
+			if !is_flag || bytes == b"-" {
+				return Some(Arg::Positional(w.value));
+			}
+			if bytes == b"--" {
+				self.only_positional = true;
+				continue;
+			}
+			let name = if bytes.starts_with(b"--") {
+				let name = &bytes[2..];
+				if let Some(i) = name.iter().position(|&b| b == b'=') {
+					return Some(Arg::KeyValue(from_bytes(&name[..i]), from_bytes(&name[i+1..])));
+				}
+				name
+			}
+			else {
+				&bytes[1..]
+			};
+			if self.value_keys.iter().any(|k| k.as_bytes() == name) {
+				if let Some(value) = self.words.next() {
+					return Some(Arg::KeyValue(from_bytes(name), value));
+				}
+			}
+			return Some(Arg::Flag(from_bytes(name)));
+		}
+	}
+}
+
+/// Parse a string into command-line arguments (using the default rules)
+///
+/// Recognises `--key=value`, `--key`, `-k`, and positional arguments (with `--` ending option parsing). Whether a
+/// flag takes a value as the next word (i.e. `--key value`) isn't known here, so either list those flags with
+/// [Args::value_keys], or call [Args::value] after them.
+///
+/// ```
+/// use cmdline_words_parser::posix::{parse_args, Arg};
+/// let mut cmdline = String::from("run --name=foo --out dir -v pos1 -- -pos2");
+/// let mut args = parse_args(&mut cmdline);
+/// assert_eq!( args.next(), Some(Arg::Positional("run")) );
+/// assert_eq!( args.next(), Some(Arg::KeyValue("name", "foo")) );
+/// assert_eq!( args.next(), Some(Arg::Flag("out")) );
+/// assert_eq!( args.value(), Some("dir") );
+/// assert_eq!( args.collect::<Vec<_>>(), [Arg::Flag("v"), Arg::Positional("pos1"), Arg::Positional("-pos2")] );
+/// ```
+pub fn parse_args<T: ?Sized + ByteString>(string: &mut T) -> Args<'_, T::OutSlice>
+{
+	Args {
+		words: crate::parse_posix(string),
+		only_positional: false,
+		value_keys: &[],
+	}
+}
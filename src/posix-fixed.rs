@@ -0,0 +1,125 @@
+//!
+//! Incremental parsing into a fixed-size buffer (for environments without an allocator)
+//!
+use super::{PosixParser, PosixShellWords, ParseError};
+
+/// Parser for input that is received in pieces, buffered in caller-provided storage
+///
+/// The buffer only needs to be large enough for the longest word (plus the separator after it). If a word doesn't
+/// fit, [FixedWordStream::next_word] returns [ParseError::TokenTooLong] and the rest of the input is discarded. As with
+/// [WordStream](super::WordStream), the words are the same as parsing all of the input at once.
+///
+/// ```
+/// let mut storage = [0; 16];
+/// let mut stream = ::cmdline_words_parser::posix::FixedWordStream::new(&mut storage, Default::default());
+/// assert_eq!( stream.feed(b"echo 'hello "), 12 );
+/// assert_eq!( stream.next_word(), Some(Ok(&b"echo"[..])) );
+/// assert_eq!( stream.next_word(), None );  // The quoted word isn't complete yet
+/// assert_eq!( stream.feed(b"world'"), 6 );
+/// stream.finish();
+/// assert_eq!( stream.next_word(), Some(Ok(&b"hello world"[..])) );
+/// assert_eq!( stream.next_word(), None );
+/// assert!( stream.is_done() );
+/// ```
+#[derive(Debug)]
+pub struct FixedWordStream<'b>
+{
+	opts: PosixParser,
+	buf: &'b mut [u8],
+	/// Number of bytes of `buf` in use
+	len: usize,
+	/// Length of the most recently returned word (removed from the buffer on the next call)
+	consumed: usize,
+	finished: bool,
+	/// Parser state carried between words (see the fields of [PosixShellWords])
+	at_command: bool,
+	done: bool,
+}
+impl<'b> FixedWordStream<'b>
+{
+	/// Create an empty stream using `buf` as storage, and the given parser options
+	pub fn new(buf: &'b mut [u8], opts: PosixParser) -> FixedWordStream<'b> {
+		FixedWordStream {
+			opts,
+			buf,
+			len: 0,
+			consumed: 0,
+			finished: false,
+			at_command: true,
+			done: false,
+		}
+	}
+
+	/// Append as much of `data` as fits in the buffer, returning the number of bytes accepted
+	///
+	/// If not all of the data fits, call [FixedWordStream::next_word] to free space then feed the rest.
+	pub fn feed(&mut self, data: &[u8]) -> usize {
+		self.compact();
+		if self.finished {
+			return 0;
+		}
+		let n = ::std::cmp::min(data.len(), self.buf.len() - self.len);
+		self.buf[self.len..][..n].copy_from_slice(&data[..n]);
+		self.len += n;
+		n
+	}
+	/// Indicate that there is no more input (so the final word is complete)
+	pub fn finish(&mut self) {
+		self.finished = true;
+	}
+	/// Returns true if the input is finished and all words have been returned
+	pub fn is_done(&self) -> bool {
+		let rest = &self.buf[self.consumed..self.len];
+		self.done || (self.finished && self.opts.separators_len(rest) == rest.len())
+	}
+
+	/// Remove the previously returned word from the buffer, along with the separators after it
+	fn compact(&mut self) {
+		let start = if self.done {
+			self.consumed
+		}
+		else {
+			self.consumed + self.opts.separators_len(&self.buf[self.consumed..self.len])
+		};
+		if start > 0 {
+			self.buf.copy_within(start .. self.len, 0);
+			self.len -= start;
+			self.consumed = 0;
+		}
+	}
+
+	/// Get the next complete word, or `None` if more input is needed (or all input has been consumed)
+	///
+	/// After an error is returned, the rest of the input is discarded.
+	pub fn next_word(&mut self) -> Option<Result<&[u8], ParseError>> {
+		if self.done {
+			return None;
+		}
+		self.compact();
+		let full = self.len == self.buf.len();
+		// Words that reach the end of the buffer aren't complete until the input is finished
+		let opts = self.opts.eof_is_final(self.finished);
+		let mut words = PosixShellWords::<[u8]>::new(&mut self.buf[..self.len], opts);
+		words.at_command = self.at_command;
+		let rv = words.next_word_result().map(|r| r.map(|w| w.value()));
+		self.consumed = words.consumed();
+		self.at_command = words.at_command;
+		self.done = words.done;
+		match rv
+		{
+		Some(Err(_)) => {
+			// Same as the word iterator, stop after an error
+			self.consumed = self.len;
+			self.finished = true;
+			},
+		None if full && !self.finished && !self.done => {
+			// The buffer is full, and the word still isn't complete
+			self.consumed = self.len;
+			self.finished = true;
+			return Some(Err(ParseError::TokenTooLong));
+			},
+		_ => {},
+		}
+		rv
+	}
+}
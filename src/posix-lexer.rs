@@ -0,0 +1,193 @@
+//!
+//! Recognition of shell operators (e.g. `|` and `&&`) between words
+//!
+use super::{step, PosixEscapeMode, PosixShellWords};
+use crate::{ByteString, ByteStringSlice};
+
+/// A command-list operator
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
+pub enum Operator
+{
+	/// `|`
+	Pipe,
+	/// `;`
+	Semicolon,
+	/// `&&`
+	And,
+	/// `||`
+	Or,
+	/// `&` (run the preceding command in the background)
+	Background,
+}
+impl Operator
+{
+	/// Recognise an operator at the start of `bytes`, returning it and its length
+	fn parse(bytes: &[u8]) -> Option<(Operator, usize)> {
+		match bytes
+		{
+		[b'|', b'|', ..] => Some((Operator::Or, 2)),
+		[b'|', ..] => Some((Operator::Pipe, 1)),
+		[b'&', b'&', ..] => Some((Operator::And, 2)),
+		// `&>` is a redirection, not a background command
+		[b'&', b'>', ..] => None,
+		[b'&', ..] => Some((Operator::Background, 1)),
+		[b';', ..] => Some((Operator::Semicolon, 1)),
+		_ => None,
+		}
+	}
+}
+
+/// Find the first unquoted (and unescaped) operator
+fn find_operator(buf: &[u8]) -> Option<(usize, Operator, usize)>
+{
+	let mut mode = PosixEscapeMode::Outer;
+	for i in 0 .. buf.len()
+	{
+		if mode == PosixEscapeMode::Outer {
+			if let Some((op, len)) = Operator::parse(&buf[i..]) {
+				return Some((i, op, len));
+			}
+		}
+		mode = step(mode, buf[i]).0;
+	}
+	None
+}
+
+/// Parse words up to the first unquoted operator, returning the words, the operator, and the rest of the string
+///
+/// If there is no operator, the entire string is parsed and the remainder is empty.
+///
+/// ```
+/// use cmdline_words_parser::posix::{parse_until_operator, Operator};
+/// let mut cmdline = String::from("cat 'a|b' | grep x");
+/// let (words, op, rest) = parse_until_operator(&mut cmdline);
+/// assert_eq!( words.collect::<Vec<_>>(), ["cat", "a|b"] );
+/// assert_eq!( op, Some(Operator::Pipe) );
+/// assert_eq!( rest, " grep x" );
+/// ```
+pub fn parse_until_operator<T: ?Sized + ByteString>(string: &mut T) -> (PosixShellWords<'_, T::OutSlice>, Option<Operator>, &mut T::OutSlice)
+{
+	// SAFE: Only split on ASCII positions, and the words are parsed with the normal rules
+	let bytes = unsafe { string.as_mut_bytes() };
+	let (head, op, tail) = match find_operator(bytes)
+		{
+		Some((pos, op, len)) => {
+			let (head, tail) = bytes.split_at_mut(pos);
+			(head, Some(op), &mut tail[len..])
+			},
+		None => {
+			let len = bytes.len();
+			let (head, tail) = bytes.split_at_mut(len);
+			(head, None, tail)
+			},
+		};
+	let rest = T::OutSlice::from_bytes_mut(tail).expect("Operator split caused UTF-8 inconsistency");
+	(PosixShellWords::new(head, super::PosixParser::new()), op, rest)
+}
+
+/// A token from [tokens]
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
+pub enum Tok<'a, T: ?Sized + 'a>
+{
+	/// An unescaped word
+	Word(&'a T),
+	/// `|`
+	Pipe,
+	/// `;`
+	Semicolon,
+	/// `&&`
+	And,
+	/// `||`
+	Or,
+	/// `>`
+	RedirectOut,
+	/// `<`
+	RedirectIn,
+	/// `>>`
+	RedirectAppend,
+	/// `&` (run in the background)
+	Background,
+	/// `&>` (redirect both stdout and stderr)
+	RedirectBoth,
+}
+impl<'a, T: ?Sized + 'a> Tok<'a, T>
+{
+	/// Recognise an operator at the start of `bytes`, returning it and its length
+	fn parse_operator(bytes: &[u8]) -> Option<(Tok<'a, T>, usize)> {
+		if let Some((op, len)) = Operator::parse(bytes) {
+			return Some((match op
+				{
+				Operator::Pipe => Tok::Pipe,
+				Operator::Semicolon => Tok::Semicolon,
+				Operator::And => Tok::And,
+				Operator::Or => Tok::Or,
+				Operator::Background => Tok::Background,
+				}, len));
+		}
+		match bytes
+		{
+		[b'&', b'>', ..] => Some((Tok::RedirectBoth, 2)),
+		[b'>', b'>', ..] => Some((Tok::RedirectAppend, 2)),
+		[b'>', ..] => Some((Tok::RedirectOut, 1)),
+		[b'<', ..] => Some((Tok::RedirectIn, 1)),
+		_ => None,
+		}
+	}
+}
+
+/// Iterator over words and operators, see [tokens]
+pub struct Tokens<'a, T: ?Sized + ByteStringSlice>
+{
+	buf: &'a mut [u8],
+	_pd: ::std::marker::PhantomData<T>,
+}
+impl<'a, T: ?Sized + ByteStringSlice + 'a> Iterator for Tokens<'a, T>
+{
+	type Item = Tok<'a, T>;
+	fn next(&mut self) -> Option<Tok<'a, T>> {
+		// 1. Skip leading separators, then check for the end
+		let start = self.buf.iter().position(|&b| !super::is_separator(b)).unwrap_or(self.buf.len());
+		crate::split_off_front_inplace_mut(&mut self.buf, start);
+		if self.buf.is_empty() {
+			return None;
+		}
+		// 2. Operator?
+		if let Some((tok, len)) = Tok::parse_operator(self.buf) {
+			crate::split_off_front_inplace_mut(&mut self.buf, len);
+			return Some(tok);
+		}
+		// 3. Find the end of the word (an unquoted separator or operator), then unescape it
+		let mut mode = PosixEscapeMode::Outer;
+		let mut end = self.buf.len();
+		for i in 0 .. self.buf.len()
+		{
+			if mode == PosixEscapeMode::Outer && (super::is_separator(self.buf[i]) || Tok::<T>::parse_operator(&self.buf[i..]).is_some()) {
+				end = i;
+				break;
+			}
+			mode = step(mode, self.buf[i]).0;
+		}
+		let word = crate::split_off_front_inplace_mut(&mut self.buf, end);
+		PosixShellWords::new(word, super::PosixParser::new()).next().map(Tok::Word)
+	}
+}
+
+/// Split a string into words and shell operators (pipes, command lists, and redirections)
+///
+/// Operators are only recognised when unquoted and unescaped, and don't need to be separated from words by spaces.
+///
+/// ```
+/// use cmdline_words_parser::posix::{tokens, Tok};
+/// let mut cmdline = String::from("cat a.txt|grep '>' >out.txt");
+/// assert_eq!( tokens(&mut cmdline).collect::<Vec<_>>(), [
+///     Tok::Word("cat"), Tok::Word("a.txt"), Tok::Pipe, Tok::Word("grep"), Tok::Word(">"), Tok::RedirectOut, Tok::Word("out.txt")
+///     ] );
+/// ```
+pub fn tokens<T: ?Sized + ByteString>(string: &mut T) -> Tokens<'_, T::OutSlice>
+{
+	Tokens {
+		// SAFE: Only split on ASCII positions, and the words are parsed with the normal rules
+		buf: unsafe { string.as_mut_bytes() },
+		_pd: ::std::marker::PhantomData,
+		}
+}
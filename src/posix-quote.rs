@@ -0,0 +1,181 @@
+//!
+//! Quoting of words so they parse back to the original (the reverse of [super::PosixShellWords])
+//!
+//! Words are wrapped in single quotes, with embedded `'` and `\` written as `'\''` and `'\\'` (closing the quote,
+//! escaping the character, then re-opening). This form parses identically with this crate and with real shells (where
+//! backslash isn't special within single quotes).
+
+/// Get the number of bytes that [quote_into] will write for `word`
+///
+/// ```
+/// use cmdline_words_parser::posix::quoted_len;
+/// assert_eq!( quoted_len(b"abc"), 5 );  // 'abc'
+/// assert_eq!( quoted_len(b"it's"), 9 );  // 'it'\''s'
+/// ```
+pub fn quoted_len(word: &[u8]) -> usize
+{
+	2 + word.iter().map(|&b| if needs_break_out(b) { 4 } else { 1 }).sum::<usize>()
+}
+
+/// Quote `word` into the start of `buf`, returning the number of bytes written
+///
+/// Returns `None` (and writes nothing) if `buf` is shorter than [quoted_len]
+///
+/// ```
+/// let mut buf = [0; 16];
+/// let len = cmdline_words_parser::posix::quote_into(b"a b", &mut buf).unwrap();
+/// assert_eq!( &buf[..len], b"'a b'" );
+/// ```
+pub fn quote_into(word: &[u8], buf: &mut [u8]) -> Option<usize>
+{
+	let len = quoted_len(word);
+	if buf.len() < len {
+		return None;
+	}
+	let mut pos = 0;
+	let mut push = |b: u8| { buf[pos] = b; pos += 1; };
+	push(b'\'');
+	for &b in word
+	{
+		if needs_break_out(b) {
+			push(b'\'');
+			push(b'\\');
+			push(b);
+			push(b'\'');
+		}
+		else {
+			push(b);
+		}
+	}
+	push(b'\'');
+	Some(len)
+}
+
+/// Quote a word into a newly allocated string
+///
+/// ```
+/// assert_eq!( cmdline_words_parser::posix::quote("it's"), r"'it'\''s'" );
+/// ```
+#[cfg(feature="alloc")]
+pub fn quote(word: &str) -> ::alloc::string::String
+{
+	let mut buf = ::alloc::vec![0; quoted_len(word.as_bytes())];
+	quote_into(word.as_bytes(), &mut buf).expect("quoted_len mismatch");
+	// NOTE: Only ASCII bytes are inserted around the input UTF-8, so this cannot fail
+	::alloc::string::String::from_utf8(buf).expect("Quoting caused UTF-8 inconsistency")
+}
+
+/// Quote a word using double quotes, escaping `"`, `\`, `$`, and `` ` `` with a backslash
+///
+/// This is the form bash expects. The default parser drops the escaped `$` and `` ` `` (they're not special to it),
+/// so words containing those only parse back identically with [crate::PosixParser::bash_double_quotes].
+///
+/// ```
+/// assert_eq!( cmdline_words_parser::posix::quote_double(r#"say "hi" $USER"#), r#""say \"hi\" \$USER""# );
+/// ```
+#[cfg(feature="alloc")]
+pub fn quote_double(word: &str) -> ::alloc::string::String
+{
+	let mut rv = ::alloc::string::String::with_capacity(word.len() + 2);
+	rv.push('"');
+	for c in word.chars()
+	{
+		if matches!(c, '"' | '\\' | '$' | '`') {
+			rv.push('\\');
+		}
+		rv.push(c);
+	}
+	rv.push('"');
+	rv
+}
+
+/// Quote a word using the shortest form (unquoted, single quotes, or double quotes) that parses back to `word`
+///
+/// The result is also valid for POSIX shells: words are only left unquoted if they contain nothing but alphanumerics
+/// and `-_./=:,+@%`, and double quotes are not used for words containing `$` or `` ` ``.
+///
+/// ```
+/// use cmdline_words_parser::posix::quote_minimal;
+/// assert_eq!( quote_minimal("abc"), "abc" );
+/// assert_eq!( quote_minimal("a b"), "'a b'" );
+/// assert_eq!( quote_minimal("it's"), r#""it's""# );
+/// ```
+#[cfg(feature="alloc")]
+pub fn quote_minimal(word: &str) -> ::alloc::string::String
+{
+	if !word.is_empty() && word.chars().all(|c| c.is_alphanumeric() || "-_./=:,+@%".contains(c)) {
+		return word.into();
+	}
+	let single_len = quoted_len(word.as_bytes());
+	if !word.contains(['$', '`']) {
+		let double_len = 2 + word.len() + word.bytes().filter(|&b| b == b'"' || b == b'\\').count();
+		if double_len < single_len {
+			let mut rv = ::alloc::string::String::with_capacity(double_len);
+			rv.push('"');
+			for c in word.chars()
+			{
+				if c == '"' || c == '\\' {
+					rv.push('\\');
+				}
+				rv.push(c);
+			}
+			rv.push('"');
+			return rv;
+		}
+	}
+	quote(word)
+}
+
+/// Quote and join a sequence of words into a single space-separated string
+///
+/// ```
+/// assert_eq!( cmdline_words_parser::posix::join(&["echo", "a b"]), "'echo' 'a b'" );
+/// ```
+#[cfg(feature="alloc")]
+pub fn join<I>(words: I) -> ::alloc::string::String
+where
+	I: IntoIterator,
+	I::Item: AsRef<str>,
+{
+	use std::fmt::Write;
+	let mut rv = ::alloc::string::String::new();
+	for (i, w) in words.into_iter().enumerate() {
+		if i != 0 {
+			rv.push(' ');
+		}
+		write!(rv, "{}", Quoted(w.as_ref())).expect("Writing to a String cannot fail");
+	}
+	rv
+}
+
+/// Formatting wrapper that writes a word in quoted form (matching [quote_into])
+///
+/// ```
+/// use cmdline_words_parser::posix::Quoted;
+/// assert_eq!( format!("cat {}", Quoted("my file")), "cat 'my file'" );
+/// ```
+#[derive(Copy,Clone,Debug)]
+pub struct Quoted<'a>(pub &'a str);
+impl<'a> ::std::fmt::Display for Quoted<'a>
+{
+	fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+		use std::fmt::Write;
+		f.write_char('\'')?;
+		let mut rest = self.0;
+		while let Some(pos) = rest.bytes().position(needs_break_out) {
+			f.write_str(&rest[..pos])?;
+			f.write_str("'\\")?;
+			f.write_str(&rest[pos..][..1])?;
+			f.write_char('\'')?;
+			rest = &rest[pos+1..];
+		}
+		f.write_str(rest)?;
+		f.write_char('\'')
+	}
+}
+
+/// Characters that can't be represented within single quotes
+fn needs_break_out(b: u8) -> bool
+{
+	b == b'\'' || b == b'\\'
+}
@@ -0,0 +1,295 @@
+//!
+//! Non-destructive scans over POSIX-escaped strings (using the default rules)
+//!
+use super::{is_posix_special, PosixEscapeMode, PosixParser};
+use std::ops::Range;
+
+/// The rules used by these scans (the same as [crate::parse_posix])
+const DEFAULT: PosixParser = PosixParser::new();
+
+/// Get the number of leading plain bytes (i.e. not [is_posix_special]) in `bytes`
+///
+/// If `high_special` is set, non-ASCII bytes are also treated as special.
+#[cfg(not(all(feature="simd", target_arch="x86_64")))]
+pub(crate) fn find_special(bytes: &[u8], high_special: bool) -> usize
+{
+	find_special_scalar(bytes, high_special)
+}
+#[cfg(all(feature="simd", target_arch="x86_64"))]
+pub(crate) fn find_special(bytes: &[u8], high_special: bool) -> usize
+{
+	// SAFE: SSE2 is always available on x86_64
+	unsafe { find_special_sse2(bytes, high_special) }
+}
+
+pub(crate) fn find_special_scalar(bytes: &[u8], high_special: bool) -> usize
+{
+	bytes.iter().position(|&b| is_posix_special(b) || (high_special && b >= 0x80)).unwrap_or(bytes.len())
+}
+
+#[cfg(all(feature="simd", target_arch="x86_64"))]
+#[target_feature(enable="sse2")]
+unsafe fn find_special_sse2(bytes: &[u8], high_special: bool) -> usize
+{
+	use std::arch::x86_64::*;
+	let mut i = 0;
+	while i + 16 <= bytes.len()
+	{
+		let v = _mm_loadu_si128(bytes.as_ptr().add(i) as *const __m128i);
+		let mut m = _mm_setzero_si128();
+		// NOTE: Must match `is_posix_special`
+		for &c in b" \t\n\r\\'\"" {
+			m = _mm_or_si128(m, _mm_cmpeq_epi8(v, _mm_set1_epi8(c as i8)));
+		}
+		if high_special {
+			// `movemask` takes the top bit of each byte, so non-ASCII bytes are included by merging in the input
+			m = _mm_or_si128(m, v);
+		}
+		let mask = _mm_movemask_epi8(m);
+		if mask != 0 {
+			return i + mask.trailing_zeros() as usize;
+		}
+		i += 16;
+	}
+	i + find_special_scalar(&bytes[i..], high_special)
+}
+
+/// Find the end of the word at the start of `buf`, returning the end offset and final escape mode
+///
+/// The end is either the first unquoted separator, or the end of the buffer.
+fn scan_word(buf: &[u8]) -> (usize, PosixEscapeMode)
+{
+	DEFAULT.word_end(buf)
+}
+
+/// Check that all quotes in `buf` are closed, and that it doesn't end with a dangling backslash (using the default
+/// rules)
+///
+/// ```
+/// use cmdline_words_parser::posix::is_balanced;
+/// assert!( is_balanced(br#"a "b" 'c'"#) );
+/// assert!( !is_balanced(br#"a "b"#) );
+/// ```
+pub fn is_balanced(buf: &[u8]) -> bool
+{
+	// Separators don't change the mode, so can be passed through the state machine
+	buf.iter().fold(PosixEscapeMode::Outer, |mode, &b| DEFAULT.step(mode, b).0) == PosixEscapeMode::Outer
+}
+
+/// Get the number of separator bytes at the start of `buf`
+fn separator_run(buf: &[u8]) -> usize
+{
+	DEFAULT.separators_len(buf)
+}
+
+/// Iterator over the raw (still escaped) extent of each word, see [token_ranges]
+pub struct TokenRanges<'a>
+{
+	buf: &'a [u8],
+	pos: usize,
+}
+impl<'a> Iterator for TokenRanges<'a>
+{
+	type Item = Range<usize>;
+	fn next(&mut self) -> Option<Range<usize>> {
+		self.pos += separator_run(&self.buf[self.pos..]);
+		if self.pos == self.buf.len() {
+			return None;
+		}
+		let start = self.pos;
+		self.pos += scan_word(&self.buf[start..]).0;
+		Some(start .. self.pos)
+	}
+}
+
+/// Get the byte ranges of each word in the (unmodified) source, including any quotes and escapes
+///
+/// ```
+/// let line = r#"foo "bar baz" qux"#;
+/// let words: Vec<_> = cmdline_words_parser::posix::token_ranges(line.as_bytes()).map(|r| &line[r]).collect();
+/// assert_eq!(words, ["foo", r#""bar baz""#, "qux"]);
+/// ```
+pub fn token_ranges(buf: &[u8]) -> TokenRanges<'_>
+{
+	TokenRanges { buf, pos: 0 }
+}
+
+/// Get the raw (still escaped) source of the last word in `buf`
+///
+/// ```
+/// let line = br#"cmd -x "file name.txt""#;
+/// assert_eq!( cmdline_words_parser::posix::last_word(line), Some(&br#""file name.txt""#[..]) );
+/// ```
+pub fn last_word(buf: &[u8]) -> Option<&[u8]>
+{
+	token_ranges(buf).last().map(|r| &buf[r])
+}
+
+/// Get the length of the longest prefix of `buf` (at most `max_bytes`) that ends at the end of a word
+///
+/// Used to shorten a command line (e.g. for a preview) without cutting a word, quote, or escape in half. A final word
+/// with an unterminated quote (or a trailing backslash) isn't complete, so the result stops at the start of it.
+///
+/// ```
+/// let line = br#"foo "bar baz" qux"#;
+/// assert_eq!( cmdline_words_parser::posix::truncate_words(line, 10), 3 );
+/// assert_eq!( cmdline_words_parser::posix::truncate_words(line, 14), 13 );
+/// assert_eq!( cmdline_words_parser::posix::truncate_words(br#"foo "bar"#, 100), 4 );
+/// ```
+pub fn truncate_words(buf: &[u8], max_bytes: usize) -> usize
+{
+	let mut rv = 0;
+	for r in token_ranges(buf)
+	{
+		if r.end > max_bytes {
+			break;
+		}
+		if scan_word(&buf[r.start..]).1 != PosixEscapeMode::Outer {
+			return r.start;
+		}
+		rv = r.end;
+	}
+	rv
+}
+
+/// Sizes of the words in a string, see [measure]
+#[derive(Copy,Clone,PartialEq,Eq,Debug,Default)]
+pub struct Measurement
+{
+	/// Number of words
+	pub word_count: usize,
+	/// Sum of the (unescaped) lengths of all words
+	pub total_unescaped_len: usize,
+	/// Length of the longest (unescaped) word
+	pub max_word_len: usize,
+}
+
+/// Get the number and unescaped sizes of the words in `buf` in a single pass, without modifying it
+///
+/// Matches the output of [crate::parse_posix], so can be used to size an allocation before parsing.
+///
+/// ```
+/// let m = cmdline_words_parser::posix::measure(br#"foo "bar baz" q\ux"#);
+/// assert_eq!(m.word_count, 3);
+/// assert_eq!(m.total_unescaped_len, 3 + 7 + 2);
+/// assert_eq!(m.max_word_len, 7);
+/// ```
+pub fn measure(buf: &[u8]) -> Measurement
+{
+	let mut rv = Measurement::default();
+	for r in token_ranges(buf)
+	{
+		let mut mode = PosixEscapeMode::Outer;
+		let mut len = 0;
+		for &byte in &buf[r]
+		{
+			let (new_mode, out) = DEFAULT.step(mode, byte);
+			mode = new_mode;
+			len += out.as_slice().len();
+		}
+		rv.word_count += 1;
+		rv.total_unescaped_len += len;
+		rv.max_word_len = ::std::cmp::max(rv.max_word_len, len);
+	}
+	rv
+}
+
+/// A parsing decision, recorded by [super::PosixShellWords::with_trace] or [trace]
+///
+/// Positions are byte offsets in the input.
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
+pub enum TraceEvent
+{
+	/// A quote (`'` or `"`) was opened
+	EnterQuote { pos: usize, quote: u8 },
+	/// The current quote was closed
+	ExitQuote { pos: usize },
+	/// The byte after a backslash, and what was emitted for it (`None` if it was dropped)
+	Escape { pos: usize, input: u8, output: Option<u8> },
+	/// A word ended (at the separator, or at the end of input)
+	TokenBoundary { pos: usize },
+}
+
+/// Record the decisions made when parsing `buf` using the default rules (as [crate::parse_posix]), without
+/// modifying it
+///
+/// See [super::PosixParser::trace] for other options.
+#[cfg(feature="alloc")]
+pub fn trace(buf: &[u8], out: &mut ::alloc::vec::Vec<TraceEvent>)
+{
+	super::PosixParser::new().trace(buf, out)
+}
+
+/// Part of a word, tagged with how it was quoted, see [segments]
+#[cfg(feature="alloc")]
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub enum Segment
+{
+	/// Outside of quotes (possibly with escapes)
+	Unquoted(::alloc::string::String),
+	/// Within single quotes
+	Single(::alloc::string::String),
+	/// Within double quotes
+	Double(::alloc::string::String),
+}
+
+/// Split each word of `line` into its (unescaped) unquoted and quoted parts, along with the index of the word
+///
+/// Uses the default rules (as [crate::parse_posix]), concatenating the parts of a word gives the parsed word.
+/// Quoted parts are included even if empty.
+///
+/// ```
+/// use cmdline_words_parser::posix::{segments, Segment};
+/// assert_eq!( segments(r#"foo"bar"baz x"#), [
+///     (0, Segment::Unquoted("foo".to_owned())),
+///     (0, Segment::Double("bar".to_owned())),
+///     (0, Segment::Unquoted("baz".to_owned())),
+///     (1, Segment::Unquoted("x".to_owned())),
+///     ] );
+/// ```
+#[cfg(feature="alloc")]
+pub fn segments(line: &str) -> ::alloc::vec::Vec<(usize, Segment)>
+{
+	fn finish(kind: PosixEscapeMode, bytes: ::alloc::vec::Vec<u8>) -> Segment {
+		// NOTE: Only ASCII is dropped when unescaping, so each part is still valid UTF-8
+		let s = ::alloc::string::String::from_utf8(bytes).expect("POSIX segmenting caused UTF-8 inconsistency");
+		match kind
+		{
+		PosixEscapeMode::SingleQuote => Segment::Single(s),
+		PosixEscapeMode::DoubleQuote => Segment::Double(s),
+		_ => Segment::Unquoted(s),
+		}
+	}
+	let buf = line.as_bytes();
+	let mut rv = ::alloc::vec::Vec::new();
+	for (index, r) in token_ranges(buf).enumerate()
+	{
+		let mut mode = PosixEscapeMode::Outer;
+		let mut cur = ::alloc::vec::Vec::new();
+		for &byte in &buf[r]
+		{
+			let (new_mode, output) = DEFAULT.step(mode, byte);
+			match (mode, new_mode)
+			{
+			(PosixEscapeMode::Outer, PosixEscapeMode::SingleQuote | PosixEscapeMode::DoubleQuote) if !cur.is_empty() => {
+				rv.push((index, finish(mode, ::std::mem::take(&mut cur))));
+				},
+			(PosixEscapeMode::SingleQuote | PosixEscapeMode::DoubleQuote, PosixEscapeMode::Outer) => {
+				rv.push((index, finish(mode, ::std::mem::take(&mut cur))));
+				},
+			_ => {},
+			}
+			cur.extend_from_slice(output.as_slice());
+			mode = new_mode;
+		}
+		// Flush the final part (an unterminated quote is still reported, even if empty)
+		match mode
+		{
+		PosixEscapeMode::Outer | PosixEscapeMode::OuterSlash if cur.is_empty() => {},
+		PosixEscapeMode::Outer | PosixEscapeMode::OuterSlash => rv.push((index, finish(PosixEscapeMode::Outer, cur))),
+		PosixEscapeMode::SingleQuote | PosixEscapeMode::SingleQuoteSlash => rv.push((index, finish(PosixEscapeMode::SingleQuote, cur))),
+		PosixEscapeMode::DoubleQuote | PosixEscapeMode::DoubleQuoteSlash => rv.push((index, finish(PosixEscapeMode::DoubleQuote, cur))),
+		}
+	}
+	rv
+}
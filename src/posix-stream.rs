@@ -0,0 +1,135 @@
+//!
+//! Incremental parsing of input that arrives in chunks (e.g. from a socket)
+//!
+use super::{PosixParser, PosixShellWords, ParseError};
+use alloc::string::String;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+/// Parser for input that is received in pieces, yielding owned words once they are complete
+///
+/// A word is complete once it's followed by a separator, or when the input is [finished](WordStream::finish). The
+/// words are the same as parsing all of the input at once (including ending at a stop token or comment).
+///
+/// ```
+/// let mut stream = ::cmdline_words_parser::posix::WordStream::new(Default::default());
+/// stream.push_str("echo 'hello ");
+/// assert_eq!( stream.next_word(), Some(Ok("echo".to_owned())) );
+/// assert_eq!( stream.next_word(), None );  // The quoted word isn't complete yet
+/// stream.push_str("world'");
+/// stream.finish();
+/// assert_eq!( stream.next_word(), Some(Ok("hello world".to_owned())) );
+/// assert_eq!( stream.next_word(), None );
+/// assert!( stream.is_done() );
+/// ```
+#[derive(Debug)]
+pub struct WordStream
+{
+	opts: PosixParser,
+	buf: String,
+	/// Start of the unparsed part of `buf` (the parsed prefix is removed when more input is pushed, or when it gets large)
+	pos: usize,
+	finished: bool,
+	waker: Option<Waker>,
+	/// Parser state carried between words (see the fields of [PosixShellWords])
+	at_command: bool,
+	done: bool,
+}
+impl WordStream
+{
+	/// Create an empty stream, using the given parser options
+	pub fn new(opts: PosixParser) -> WordStream {
+		WordStream {
+			opts,
+			buf: String::new(),
+			pos: 0,
+			finished: false,
+			waker: None,
+			at_command: true,
+			done: false,
+		}
+	}
+
+	/// Append more input
+	pub fn push_str(&mut self, s: &str) {
+		self.compact();
+		self.buf.push_str(s);
+		self.wake();
+	}
+	/// Indicate that there is no more input (so the final word is complete)
+	pub fn finish(&mut self) {
+		self.finished = true;
+		self.wake();
+	}
+	/// Returns true if the input is finished and all words have been returned
+	pub fn is_done(&self) -> bool {
+		let rest = &self.buf.as_bytes()[self.pos..];
+		self.done || (self.finished && self.opts.separators_len(rest) == rest.len())
+	}
+
+	/// Remove the parsed prefix of the buffer
+	fn compact(&mut self) {
+		self.buf.drain(.. self.pos);
+		self.pos = 0;
+	}
+
+	fn wake(&mut self) {
+		if let Some(w) = self.waker.take() {
+			w.wake();
+		}
+	}
+
+	/// Get the next complete word, or `None` if more input is needed (or all input has been consumed)
+	///
+	/// After an error is returned, the rest of the input is discarded.
+	pub fn next_word(&mut self) -> Option<Result<String, ParseError>> {
+		if self.done {
+			return None;
+		}
+		// Words that reach the end of the buffer aren't complete until the input is finished
+		let opts = self.opts.eof_is_final(self.finished);
+		// SAFE: The parser keeps the buffer valid UTF-8 (the parsed part is only replaced with ASCII)
+		let mut words = PosixShellWords::<str>::new(unsafe { &mut self.buf.as_mut_vec()[self.pos..] }, opts);
+		words.at_command = self.at_command;
+		let rv = words.next_word_result().map(|r| r.map(|w| String::from(w.value())));
+		self.pos += words.consumed();
+		self.at_command = words.at_command;
+		self.done = words.done;
+		if let Some(Err(_)) = rv {
+			// Same as the word iterator, stop after an error
+			self.buf.clear();
+			self.pos = 0;
+			self.finished = true;
+		}
+		else if self.pos > self.buf.len() / 2 {
+			self.compact();
+		}
+		rv
+	}
+
+	/// Poll for the next word, registering the waker to be notified when more input arrives
+	///
+	/// This is the implementation of `futures_core::Stream` (with the `futures` feature).
+	pub fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<String, ParseError>>> {
+		let this = self.get_mut();
+		match this.next_word()
+		{
+		Some(w) => Poll::Ready(Some(w)),
+		None if this.finished || this.done => Poll::Ready(None),
+		None => {
+			this.waker = Some(cx.waker().clone());
+			Poll::Pending
+			},
+		}
+	}
+}
+
+/// Words as they become available, ending once the input is [finished](WordStream::finish)
+#[cfg(feature="futures")]
+impl ::futures_core::Stream for WordStream
+{
+	type Item = Result<String, ParseError>;
+	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+		WordStream::poll_next(self, cx)
+	}
+}
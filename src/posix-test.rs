@@ -2,6 +2,8 @@
 //! Tests for the POSIX/UNIX parser
 //!
 use crate::parse_posix;
+#[cfg(not(feature="std"))]
+use alloc::{format, vec, string::String, vec::Vec, borrow::ToOwned};
 
 #[test]
 fn non_escaped()
@@ -34,3 +36,2036 @@ fn semi_complex()
 	assert_eq!(iter.next(), Some("escaped string"));
 	assert_eq!(iter.next(), None);
 }
+
+#[test]
+fn step_transitions()
+{
+	use super::{step, PosixEscapeMode as M};
+	assert_eq!(step(M::Outer, b'a'), (M::Outer, Some(b'a')));
+	assert_eq!(step(M::Outer, b'\\'), (M::OuterSlash, None));
+	assert_eq!(step(M::OuterSlash, b' '), (M::Outer, Some(b' ')));
+	assert_eq!(step(M::OuterSlash, b'n'), (M::Outer, Some(b'\n')));
+	assert_eq!(step(M::Outer, b'\''), (M::SingleQuote, None));
+	assert_eq!(step(M::SingleQuote, b'"'), (M::SingleQuote, Some(b'"')));
+	assert_eq!(step(M::SingleQuoteSlash, b'x'), (M::SingleQuote, None));
+	assert_eq!(step(M::DoubleQuote, b'"'), (M::Outer, None));
+	assert_eq!(step(M::DoubleQuoteSlash, b't'), (M::DoubleQuote, Some(b'\t')));
+}
+
+#[test]
+fn unicode_whitespace()
+{
+	let mut s = String::from("Hello\u{A0}world\u{3000}\u{3000}again");
+	let mut iter = crate::PosixParser::new().unicode_whitespace(true).parse(&mut s);
+	assert_eq!(iter.next(), Some("Hello"));
+	assert_eq!(iter.next(), Some("world"));
+	assert_eq!(iter.next(), Some("again"));
+	assert_eq!(iter.next(), None);
+
+	// Off by default
+	let mut s = String::from("Hello\u{A0}world");
+	let mut iter = parse_posix(&mut s);
+	assert_eq!(iter.next(), Some("Hello\u{A0}world"));
+	assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn quoted_len_matches()
+{
+	use super::{quoted_len, quote_into};
+	let words: &[&[u8]] = &[b"", b"a", b"a b", b"it's", b"\\", b"''", b"\\'\\", b"\"double\"", b"tab\there", "caf\u{e9}".as_bytes()];
+	for w in words
+	{
+		let mut buf = [0; 64];
+		assert_eq!(quote_into(w, &mut buf), Some(quoted_len(w)), "{:?}", w);
+		assert_eq!(quote_into(w, &mut buf[..quoted_len(w)-1]), None);
+		// And it parses back
+		let mut buf = buf[..quoted_len(w)].to_vec();
+		let mut iter = parse_posix(&mut buf[..]);
+		assert_eq!(iter.next(), Some(&w[..]));
+		assert_eq!(iter.next(), None);
+	}
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn try_complete()
+{
+	use super::{try_complete, Incomplete};
+	assert_eq!(try_complete(&mut String::from("echo \"a b\" c")), Ok(vec!["echo".to_owned(), "a b".to_owned(), "c".to_owned()]));
+	assert_eq!(try_complete(&mut String::from("echo 'a b")), Err(Incomplete::SingleQuote));
+	assert_eq!(try_complete(&mut String::from("echo \"a b")), Err(Incomplete::DoubleQuote));
+	assert_eq!(try_complete(&mut String::from("echo a\\")), Err(Incomplete::Escape));
+}
+
+#[test]
+fn cr_mode()
+{
+	use super::{PosixParser, CrMode};
+	let mut s = String::from("a\rb c");
+	let mut iter = PosixParser::new().parse(&mut s);
+	assert_eq!(iter.next(), Some("a"));
+	assert_eq!(iter.next(), Some("b"));
+	assert_eq!(iter.next(), Some("c"));
+	assert_eq!(iter.next(), None);
+
+	let mut s = String::from("a\rb \r c\r\n");
+	let mut iter = PosixParser::new().cr_mode(CrMode::Ignore).parse(&mut s);
+	assert_eq!(iter.next(), Some("ab"));
+	assert_eq!(iter.next(), Some("c"));
+	assert_eq!(iter.next(), None);
+
+	let mut s = String::from("a\rb c");
+	let mut iter = PosixParser::new().cr_mode(CrMode::Literal).parse(&mut s);
+	assert_eq!(iter.next(), Some("a\rb"));
+	assert_eq!(iter.next(), Some("c"));
+	assert_eq!(iter.next(), None);
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn for_each_into()
+{
+	let mut s = String::from("a 'b c' d");
+	let mut out = String::new();
+	parse_posix(&mut s).for_each_into("|", &mut out).unwrap();
+	assert_eq!(out, "a|b c|d");
+
+	let mut s = *b"x \xFF\\ y";
+	let mut out = String::new();
+	parse_posix(&mut s[..]).for_each_into("|", &mut out).unwrap();
+	assert_eq!(out, "x|\u{FFFD} y");
+	// A truncated sequence is also replaced (and one at the end of the word)
+	let mut s = *b"\xE2\x80a\xC3\xA9\xF0\x9F b";
+	let mut out = String::new();
+	parse_posix(&mut s[..]).for_each_into("|", &mut out).unwrap();
+	assert_eq!(out, "\u{FFFD}a\u{e9}\u{FFFD}|b");
+}
+
+#[test]
+fn smart_quotes()
+{
+	let mut s = String::from("echo \u{201C}a b\u{201D} \u{2018}it\u{201D}s\u{2019} \u{201C}x");
+	let mut iter = crate::PosixParser::new().map_smart_quotes(true).parse(&mut s);
+	assert_eq!(iter.next(), Some("echo"));
+	assert_eq!(iter.next(), Some("a b"));
+	assert_eq!(iter.next(), Some("it\"s"));
+	assert_eq!(iter.next(), Some("x"));
+	assert_eq!(iter.next(), None);
+	assert!(::std::str::from_utf8(s.as_bytes()).is_ok());
+
+	// Off by default
+	let mut s = String::from("\u{201C}a b\u{201D}");
+	let mut iter = parse_posix(&mut s);
+	assert_eq!(iter.next(), Some("\u{201C}a"));
+	assert_eq!(iter.next(), Some("b\u{201D}"));
+	assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn token_ranges()
+{
+	let line = "  foo \"bar baz\" qu\\ x ";
+	let ranges: Vec<_> = super::token_ranges(line.as_bytes()).collect();
+	assert_eq!(ranges, [2..5, 6..15, 16..21]);
+	assert_eq!(&line[ranges[1].clone()], "\"bar baz\"");
+	assert_eq!(&line[ranges[2].clone()], "qu\\ x");
+	assert_eq!(super::token_ranges(b"").count(), 0);
+	assert_eq!(super::token_ranges(b" \t ").count(), 0);
+}
+
+#[test]
+fn quote_adjacency()
+{
+	let mut s = String::from(r#"'a'\''b' 'a'"b" "a"'b'c a\'b 'a''b'"#);
+	let mut iter = parse_posix(&mut s);
+	assert_eq!(iter.next(), Some("a'b"));
+	assert_eq!(iter.next(), Some("ab"));
+	assert_eq!(iter.next(), Some("abc"));
+	assert_eq!(iter.next(), Some("a'b"));
+	assert_eq!(iter.next(), Some("ab"));
+	assert_eq!(iter.next(), None);
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn quoted_display()
+{
+	use super::{quote, Quoted};
+	let mut s = format!("{} {}", Quoted("a b"), Quoted("c"));
+	assert_eq!(s, "'a b' 'c'");
+	let mut iter = parse_posix(&mut s);
+	assert_eq!(iter.next(), Some("a b"));
+	assert_eq!(iter.next(), Some("c"));
+	assert_eq!(iter.next(), None);
+
+	for w in ["", "it's", "back\\slash", "''", "\\'"] {
+		assert_eq!(format!("{}", Quoted(w)), quote(w));
+	}
+}
+
+#[test]
+fn quoted_leading_space()
+{
+	let mut s = String::from("a \" b\"  '  c' \"\"d");
+	let mut iter = parse_posix(&mut s);
+	assert_eq!(iter.next(), Some("a"));
+	assert_eq!(iter.next(), Some(" b"));
+	assert_eq!(iter.next(), Some("  c"));
+	assert_eq!(iter.next(), Some("d"));
+	assert_eq!(iter.next(), None);
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn to_vec()
+{
+	let mut s = String::from("a 'b c' d\\ e");
+	assert_eq!(parse_posix(&mut s).to_vec(), ["a", "b c", "d e"]);
+	let mut s = *b"a 'b c' \xFF";
+	assert_eq!(parse_posix(&mut s[..]).to_vec(), [&b"a"[..], b"b c", b"\xFF"]);
+}
+#[test]
+#[cfg(feature="alloc")]
+fn to_vec_capacity()
+{
+	let mut s = String::from(r#"gcc -O2 "-DNAME=a b" main.c\ x.c 'util.c'"#);
+	let words = parse_posix(&mut s).to_vec();
+	assert_eq!(words.len(), 5);
+	assert_eq!(words.capacity(), words.len());
+	let mut b = b"a:b:c d".to_vec();
+	let words = crate::PosixParser::new().soft_separator(b':').parse(&mut b[..]).to_vec();
+	assert_eq!(words.len(), 4);
+	assert_eq!(words.capacity(), words.len());
+}
+
+#[test]
+fn looks_like_path()
+{
+	for &(cmd, is_path) in &[("ls -l", false), ("./ls", true), ("/bin/ls a/b", true), ("\"a/b\"", true)]
+	{
+		let mut s = String::from(cmd);
+		let w = parse_posix(&mut s).next_word().unwrap();
+		assert_eq!(w.looks_like_path(), is_path, "{:?}", cmd);
+	}
+}
+
+#[test]
+fn consumed_len()
+{
+	let mut s = String::from("ab  'c d' e");
+	let mut iter = parse_posix(&mut s);
+	assert_eq!((iter.original_len(), iter.consumed()), (11, 0));
+	assert_eq!(iter.next(), Some("ab"));
+	assert_eq!((iter.original_len(), iter.consumed()), (11, 4));
+	assert_eq!(iter.next(), Some("c d"));
+	assert_eq!((iter.original_len(), iter.consumed()), (11, 10));
+	assert_eq!(iter.next(), Some("e"));
+	assert_eq!((iter.original_len(), iter.consumed()), (11, 11));
+}
+
+#[test]
+fn escaped_spaces_in_flags()
+{
+	let mut s = String::from(r"--flag=a\ b key=val\ ue a\ b\ c");
+	let mut iter = parse_posix(&mut s);
+	assert_eq!(iter.next(), Some("--flag=a b"));
+	assert_eq!(iter.next(), Some("key=val ue"));
+	assert_eq!(iter.next(), Some("a b c"));
+	assert_eq!(iter.next(), None);
+}
+
+/// The fast path (copying runs of plain bytes) is disabled by a token length limit
+fn parse_slow(s: &mut String) -> Vec<&str>
+{
+	crate::PosixParser::new().max_token_len(usize::MAX - 1).parse(s).collect()
+}
+
+#[test]
+fn fast_path_matches()
+{
+	let inputs = [
+		"Hello world",
+		r##"Hello world "double quoted (\")" '"single quoted (\')"'  escaped\ string"##,
+		r"a\tb\n 'c\'d' \q",
+		"tabs\tand\nnewlines\r\n",
+		];
+	for input in &inputs
+	{
+		let mut a = String::from(*input);
+		let mut b = String::from(*input);
+		let direct: Vec<&str> = parse_posix(&mut a).collect();
+		assert_eq!(direct, parse_slow(&mut b), "{:?}", input);
+	}
+}
+
+/// The shared dialect driver gives the same words (using the inputs from the tests of the default rules)
+#[test]
+fn dialect_matches()
+{
+	let inputs = [
+		"Hello world",
+		"Hello\\ world",
+		"a\rb \r c\r\n",
+		"a 'b c' d\\ e",
+		r#"'a'\''b' 'a'"b" "a"'b'c a\'b 'a''b'"#,
+		"a \" b\"  '  c' \"\"d",
+		r#"gcc -O2 "-DNAME=a b" main.c\ x.c 'util.c'"#,
+		r"--flag=a\ b key=val\ ue a\ b\ c",
+		"a 'unclosed",
+		"abc 'abcdef' ab\u{e9}d",
+		"  cp -r \"src dir\" dst",
+		"\\q \\$HOME \\* \\n a\\\nb \"\\q\"",
+		r#"\n \t "\n" "a\tb" "\$\`\"\\" "x\q" 'a\nb'"#,
+		"\"\\$\" \"\\`\" \"\\\"\" \"\\\\\" \"a\\\nb\" \"\\x\\n\" \\t",
+		r#""a b" 'c\\d' e"f" "g"'h' "" 'i'"#,
+		r#"'he said "hi"' "it's here" "'"'"'"#,
+		r#"ECHO Hello "QUOTED Ünïcode" \Escaped\ X"#,
+		"\\\u{e9} '\\\u{e9}' \"\\\u{1F600}\"",
+		];
+	for input in &inputs
+	{
+		let mut a = String::from(*input);
+		let mut b = String::from(*input);
+		let direct: Vec<&str> = parse_posix(&mut a).collect();
+		let dialect: Vec<&str> = crate::parse(super::PosixDialect, &mut b).collect();
+		assert_eq!(direct, dialect, "{:?}", input);
+	}
+}
+
+/// Minimal xorshift generator, for reproducible randomised tests
+struct Rng(u64);
+impl Rng {
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+	fn below(&mut self, n: usize) -> usize {
+		(self.next() % n as u64) as usize
+	}
+	/// Random word built from characters that exercise the escaping rules
+	fn word(&mut self) -> String {
+		const CHARS: &[&str] = &["a", "Z", "0", " ", "\t", "\n", "\r", "'", "\"", "\\", "$", "#", "n", "t", "\u{e9}", "\u{1F600}"];
+		(0 .. self.below(8)).map(|_| CHARS[self.below(CHARS.len())]).collect()
+	}
+}
+
+/// Randomised property tests, including a comparison against `shlex` (slower, so opt-in)
+///
+/// Run with `cargo test --features proptest-tests`.
+#[cfg(feature="proptest-tests")]
+mod proptests
+{
+	use super::parse_posix;
+
+	/// Words built from characters that exercise the escaping rules, or from any characters at all
+	fn word_strategy() -> impl ::proptest::strategy::Strategy<Value=String>
+	{
+		prop_oneof![
+			"[aZ0 \t\n\r'\"\\\\$#nt\u{e9}\u{1F600}]{0,8}",
+			"(?s).{0,8}",
+			]
+	}
+
+	/// Input in the subset of the syntax that `shlex` parses the same way as this crate
+	///
+	/// Intentional divergences (which are never generated):
+	/// - `\n`/`\t`/`\r` escapes (outside and within double quotes) are control characters here, not letters
+	/// - Within single quotes, `\'` and `\\` are escapes here, not literal backslashes
+	/// - Unknown escapes (e.g. `\x`, and `\$` within double quotes) are dropped here, instead of kept as `x` or `\$`
+	/// - A backslash-newline is a literal newline here, not a line continuation
+	/// - `#` doesn't start a comment by default, and `\r` is a separator
+	/// - Unterminated quotes and trailing backslashes are accepted here (`shlex` returns `None`)
+	fn shlex_input() -> impl ::proptest::strategy::Strategy<Value=String>
+	{
+		use proptest::prelude::*;
+		let piece = prop_oneof![
+			"[aZ0$\u{e9} \t\n]{1,4}",
+			"\\\\[ \t'\"\\\\\u{e9}]",
+			"'[aZ \t\n\"$\u{e9}]*'",
+			"\"([aZ \t\n'$\u{e9}]|\\\\[\"\\\\])*\"",
+			];
+		proptest::collection::vec(piece, 0..12).prop_map(|v| v.concat())
+	}
+
+	proptest! {
+		/// Round-trip property: quoting then parsing any list of words yields the same words
+		#[test]
+		fn proptest_round_trip(words in proptest::collection::vec(word_strategy(), 1..6))
+		{
+			let mut joined = super::super::join(&words);
+			let parsed: Vec<&str> = parse_posix(&mut joined).collect();
+			prop_assert_eq!(parsed, words);
+		}
+
+		/// Raw (possibly malformed) input never produces more output than input, and leaves the buffer valid
+		#[test]
+		fn proptest_input_shrinks(words in proptest::collection::vec(word_strategy(), 1..6))
+		{
+			let input = words.join(" ");
+			let mut buf = input.clone();
+			let total: usize = parse_posix(&mut buf).map(|w| w.len()).sum();
+			prop_assert!(total <= input.len(), "{:?}", input);
+			prop_assert!(::std::str::from_utf8(buf.as_bytes()).is_ok(), "{:?}", input);
+		}
+
+		/// Differential test against `shlex`, on the subset of the syntax where they agree
+		#[test]
+		fn proptest_matches_shlex(input in shlex_input())
+		{
+			let expected = ::shlex::split(&input);
+			prop_assert!(expected.is_some(), "{:?}", input);
+			let mut buf = input.clone();
+			let parsed: Vec<&str> = parse_posix(&mut buf).collect();
+			prop_assert_eq!(parsed, expected.unwrap(), "{:?}", input);
+		}
+	}
+}
+
+#[test]
+fn on_unterminated()
+{
+	use super::{PosixParser, Unterminated, ParseError};
+	let mut s = String::from("a 'unclosed");
+	let mut iter = PosixParser::new().parse(&mut s);
+	assert_eq!(iter.next_result(), Some(Ok("a")));
+	assert_eq!(iter.next_result(), Some(Ok("unclosed")));
+	assert_eq!(iter.next_result(), None);
+
+	let mut s = String::from("a 'unclosed");
+	let mut iter = PosixParser::new().on_unterminated(Unterminated::Error).parse(&mut s);
+	assert_eq!(iter.next_result(), Some(Ok("a")));
+	assert_eq!(iter.next_result(), Some(Err(ParseError::UnterminatedQuote)));
+
+	let mut s = String::from("a 'unclosed");
+	let mut iter = PosixParser::new().on_unterminated(Unterminated::Drop).parse(&mut s);
+	assert_eq!(iter.next_result(), Some(Ok("a")));
+	assert_eq!(iter.next_result(), None);
+}
+
+#[test]
+fn max_token_len()
+{
+	use super::{PosixParser, TooLong, ParseError};
+	let mut s = String::from("abc 'abcdef' ab\u{e9}d");
+	let mut iter = PosixParser::new().max_token_len(3).parse(&mut s);
+	assert_eq!(iter.next_result(), Some(Ok("abc")));
+	assert_eq!(iter.next_result(), Some(Err(ParseError::TokenTooLong)));
+	// Poisoned after the error
+	assert_eq!(iter.next_result(), None);
+	assert!(!iter.skip_word());
+
+	let mut s = String::from("abc 'abcdef' ab\u{e9}d");
+	let mut iter = PosixParser::new().max_token_len(3).on_token_too_long(TooLong::Truncate).parse(&mut s);
+	assert_eq!(iter.next_result(), Some(Ok("abc")));
+	assert_eq!(iter.next_result(), Some(Ok("abc")));
+	// Doesn't split the multi-byte character
+	assert_eq!(iter.next_result(), Some(Ok("ab")));
+	assert_eq!(iter.next_result(), None);
+	assert!(::std::str::from_utf8(s.as_bytes()).is_ok());
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn collect_indexed()
+{
+	let mut s = String::from("  cp -r \"src dir\" dst");
+	let words = parse_posix(&mut s).collect_indexed();
+	assert_eq!(words.len(), 4);
+	assert_eq!(words.get(0), Some("cp"));
+	assert_eq!(words.get(2), Some("src dir"));
+	assert_eq!(words.get(3), Some("dst"));
+	assert_eq!(words.get(4), None);
+	assert_eq!(words.span(0), Some(2..4));
+	assert_eq!(words.span(2), Some(8..15));
+	assert_eq!(&s[8..15], "src dir");
+
+	// Spans are after trimming
+	let mut s = String::from("' a' b");
+	let words = crate::PosixParser::new().trim_tokens(true).parse(&mut s).collect_indexed();
+	assert_eq!(words.get(0), Some("a"));
+	assert_eq!(words.span(0), Some(1..2));
+	assert_eq!(words.get(1), Some("b"));
+	assert_eq!(words.span(1), Some(5..6));
+	assert_eq!(&s[1..2], "a");
+}
+
+#[test]
+fn bash_compatible_escapes()
+{
+	let mut s = String::from("\\q \\$HOME \\* \\n a\\\nb \"\\q\"");
+	let mut iter = crate::PosixParser::new().bash_compatible_escapes(true).parse(&mut s);
+	assert_eq!(iter.next(), Some("q"));
+	assert_eq!(iter.next(), Some("$HOME"));
+	assert_eq!(iter.next(), Some("*"));
+	assert_eq!(iter.next(), Some("n"));
+	assert_eq!(iter.next(), Some("ab"));
+	// Only affects unquoted escapes
+	assert_eq!(iter.next(), Some(""));
+	assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn shell_accurate_escapes()
+{
+	let mut s = String::from(r#"\n \t "\n" "a\tb" "\$\`\"\\" "x\q" 'a\nb'"#);
+	let mut iter = crate::PosixParser::new().shell_accurate_escapes(true).parse(&mut s);
+	assert_eq!(iter.next(), Some("n"));
+	assert_eq!(iter.next(), Some("t"));
+	assert_eq!(iter.next(), Some(r"\n"));
+	assert_eq!(iter.next(), Some(r"a\tb"));
+	assert_eq!(iter.next(), Some(r#"$`"\"#));
+	assert_eq!(iter.next(), Some(r"x\q"));
+	// Single quotes are unaffected
+	assert_eq!(iter.next(), Some("ab"));
+	assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn bash_double_quotes()
+{
+	let mut s = String::from("\"\\$\" \"\\`\" \"\\\"\" \"\\\\\" \"a\\\nb\" \"\\x\\n\" \\t");
+	let mut iter = crate::PosixParser::new().bash_double_quotes(true).parse(&mut s);
+	assert_eq!(iter.next(), Some("$"));
+	assert_eq!(iter.next(), Some("`"));
+	assert_eq!(iter.next(), Some("\""));
+	assert_eq!(iter.next(), Some("\\"));
+	assert_eq!(iter.next(), Some("ab"));
+	assert_eq!(iter.next(), Some(r"\x\n"));
+	// Unquoted escapes are unaffected
+	assert_eq!(iter.next(), Some("\t"));
+	assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn parse_until_operator()
+{
+	use super::{parse_until_operator, Operator};
+	let mut s = String::from("a | b && c; d || e");
+	let (words, op, rest) = parse_until_operator(&mut s);
+	assert_eq!(words.collect::<Vec<_>>(), ["a"]);
+	assert_eq!(op, Some(Operator::Pipe));
+	let (words, op, rest) = parse_until_operator(rest);
+	assert_eq!(words.collect::<Vec<_>>(), ["b"]);
+	assert_eq!(op, Some(Operator::And));
+	let (words, op, rest) = parse_until_operator(rest);
+	assert_eq!(words.collect::<Vec<_>>(), ["c"]);
+	assert_eq!(op, Some(Operator::Semicolon));
+	let (words, op, rest) = parse_until_operator(rest);
+	assert_eq!(words.collect::<Vec<_>>(), ["d"]);
+	assert_eq!(op, Some(Operator::Or));
+	let (words, op, rest) = parse_until_operator(rest);
+	assert_eq!(words.collect::<Vec<_>>(), ["e"]);
+	assert_eq!(op, None);
+	assert_eq!(rest, "");
+
+	// Quoted operators are literal
+	let mut s = String::from("a \"|\" b'&&'c");
+	let (words, op, _) = parse_until_operator(&mut s);
+	assert_eq!(words.collect::<Vec<_>>(), ["a", "|", "b&&c"]);
+	assert_eq!(op, None);
+}
+
+#[test]
+fn leading_separators()
+{
+	let mut s = String::from(" \t a  b");
+	let mut iter = parse_posix(&mut s);
+	assert_eq!(iter.next(), Some("a"));
+	assert_eq!(iter.next(), Some("b"));
+	assert_eq!(iter.next(), None);
+
+	let mut s = String::from("  \n ");
+	assert_eq!(parse_posix(&mut s).next(), None);
+}
+
+#[test]
+fn tokens()
+{
+	use super::{tokens, Tok};
+	let mut s = String::from("cat a.txt | grep x > out.txt");
+	assert_eq!(tokens(&mut s).collect::<Vec<_>>(), [
+		Tok::Word("cat"), Tok::Word("a.txt"), Tok::Pipe, Tok::Word("grep"), Tok::Word("x"), Tok::RedirectOut, Tok::Word("out.txt")
+		]);
+	let mut s = String::from("a>>b<c;d&&e||\"f|g\"");
+	assert_eq!(tokens(&mut s).collect::<Vec<_>>(), [
+		Tok::Word("a"), Tok::RedirectAppend, Tok::Word("b"), Tok::RedirectIn, Tok::Word("c"), Tok::Semicolon,
+		Tok::Word("d"), Tok::And, Tok::Word("e"), Tok::Or, Tok::Word("f|g"),
+		]);
+}
+
+#[test]
+fn keep_enclosing_quotes()
+{
+	let mut s = String::from(r#""a b" 'c\\d' e"f" "g"'h' "" 'i'"#);
+	let mut iter = crate::PosixParser::new().keep_enclosing_quotes(true).parse(&mut s);
+	assert_eq!(iter.next(), Some("\"a b\""));
+	assert_eq!(iter.next(), Some("'c\\d'"));
+	assert_eq!(iter.next(), Some("ef"));
+	assert_eq!(iter.next(), Some("gh"));
+	assert_eq!(iter.next(), Some("\"\""));
+	assert_eq!(iter.next(), Some("'i'"));
+	assert_eq!(iter.next(), None);
+}
+
+/// The fast path (copying runs of plain bytes) matches the byte-at-a-time path, and the dialect driver
+#[test]
+fn random_matches_slow_path()
+{
+	let mut rng = Rng(0xD1B5_4A32_D192_ED03);
+	for _ in 0 .. 2000
+	{
+		let input: String = (0 .. 1 + rng.below(20)).map(|_| rng.word()).collect::<Vec<_>>().join(if rng.below(2) == 0 { " " } else { "" });
+		let mut a = input.clone();
+		let mut b = input.clone();
+		let mut c = input.clone();
+		let fast: Vec<&str> = parse_posix(&mut a).collect();
+		assert_eq!(fast, parse_slow(&mut b), "{:?}", input);
+		let dialect: Vec<&str> = crate::parse(super::PosixDialect, &mut c).collect();
+		assert_eq!(fast, dialect, "{:?}", input);
+	}
+}
+
+#[test]
+fn find_special()
+{
+	let mut rng = Rng(0x1234_5678_9ABC_DEF1);
+	for _ in 0 .. 2000
+	{
+		let input: String = (0 .. rng.below(40)).map(|_| if rng.below(4) == 0 { rng.word() } else { "abcdefgh".into() }).collect();
+		for &high in &[false, true] {
+			assert_eq!(super::scan::find_special(input.as_bytes(), high), super::scan::find_special_scalar(input.as_bytes(), high), "{:?}", input);
+		}
+	}
+}
+
+/// Fuzz-style invariant check: any input under any option set must not panic, and never grows
+#[test]
+fn random_bytes_invariants()
+{
+	let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+	for _ in 0 .. 20000
+	{
+		let mut opts = super::PosixParser::new()
+			.unicode_whitespace(rng.below(2) == 0)
+			.map_smart_quotes(rng.below(2) == 0)
+			.bash_compatible_escapes(rng.below(2) == 0)
+			.keep_enclosing_quotes(rng.below(2) == 0)
+			.cr_mode([super::CrMode::Separator, super::CrMode::Ignore, super::CrMode::Literal][rng.below(3)])
+			.on_unterminated([super::Unterminated::Accept, super::Unterminated::Error, super::Unterminated::Drop][rng.below(3)])
+			;
+		if rng.below(2) == 0 {
+			opts = opts.shell_accurate_escapes(true);
+		}
+		if rng.below(3) == 0 {
+			opts = opts.max_token_len(rng.below(6)).on_token_too_long([super::TooLong::Error, super::TooLong::Truncate][rng.below(2)]);
+		}
+		// Strings
+		let input: String = (0 .. 1 + rng.below(6)).map(|_| rng.word()).collect::<Vec<_>>().concat() + ["", "\u{2018}", "\u{201D}", "\u{a0}", "\u{3000}"][rng.below(5)];
+		let mut s = input.clone();
+		let total: usize = opts.parse(&mut s).map(|w| w.len()).sum();
+		assert!(total <= input.len(), "{:?}", input);
+		// Raw bytes
+		let bytes: Vec<u8> = (0 .. rng.below(24)).map(|_| [b'\\', b'\'', b'"', b' ', b'\r', b'\n', 0xE2, 0x80, 0x98, rng.next() as u8][rng.below(10)]).collect();
+		let mut b = bytes.clone();
+		let total: usize = opts.parse(&mut b[..]).map(|w| w.len()).sum();
+		assert!(total <= bytes.len(), "{:?}", bytes);
+	}
+}
+
+#[test]
+fn soft_separator()
+{
+	let parser = super::PosixParser::new().soft_separator(b':');
+	let words = |s: &str| -> Vec<(String, Option<u8>)> {
+		let mut buf = String::from(s);
+		let mut words = parser.parse(&mut buf);
+		let mut rv = Vec::new();
+		while let Some(w) = words.next_word() {
+			rv.push( (w.value().to_owned(), w.trailing_separator()) );
+		}
+		rv
+	};
+	let c = Some(b':');
+	assert_eq!(words("a:b:c"), [("a".into(), c), ("b".into(), c), ("c".into(), None)]);
+	assert_eq!(words("/usr/bin::/bin"), [("/usr/bin".into(), c), ("".into(), c), ("/bin".into(), None)]);
+	// Quoted and escaped separators don't split
+	assert_eq!(words(r#"'C:x':"D:y""#), [("C:x".into(), c), ("D:y".into(), None)]);
+	// Whitespace still splits (without a trailing separator)
+	assert_eq!(words("a b:c"), [("a".into(), None), ("b".into(), c), ("c".into(), None)]);
+}
+
+#[test]
+fn soft_separator_ifs()
+{
+	// Matches `IFS=", "` field splitting, e.g. `IFS=", "; set -- $v; printf '<%s>' "$@"`
+	let parser = super::PosixParser::new().soft_separator(b',');
+	let words = |s: &str| -> Vec<String> {
+		let mut buf = String::from(s);
+		parser.parse(&mut buf).map(|w| w.to_owned()).collect()
+	};
+	assert_eq!(words("a,,b c"), ["a", "", "b", "c"]);
+	assert_eq!(words("a , b"), ["a", "b"]);
+	assert_eq!(words("a , ,b"), ["a", "", "b"]);
+	assert_eq!(words(" ,a, "), ["", "a"]);
+	assert_eq!(words("a  b ,"), ["a", "b"]);
+	// Skipping and counting use the same boundaries
+	let mut buf = String::from("a , b");
+	let mut iter = parser.parse(&mut buf);
+	assert!(iter.skip_word());
+	assert_eq!(iter.collect::<Vec<_>>(), ["b"]);
+	let mut buf = String::from("a ,, b");
+	assert_eq!(parser.parse(&mut buf).to_vec(), ["a", "", "b"]);
+	let mut buf = String::from("a , b ,");
+	assert_eq!(parser.parse(&mut buf).pop_word(), Some("b"));
+	let mut buf = String::from("a END , b");
+	let mut iter = parser.stop_token(b"END").parse(&mut buf);
+	assert_eq!(iter.by_ref().collect::<Vec<_>>(), ["a"]);
+	assert_eq!(iter.remainder(), "b");
+}
+
+#[test]
+fn skip_word()
+{
+	let mut buf = String::from(r#"  "quoted program" second\ word 'third'"#);
+	let mut words = parse_posix(&mut buf);
+	assert!(words.skip_word());
+	assert_eq!(words.next(), Some("second word"));
+	assert!(words.skip_word());
+	assert!(!words.skip_word());
+	assert_eq!(words.next(), None);
+
+	let mut buf = String::from("a:'b:c':d");
+	let mut words = super::PosixParser::new().soft_separator(b':').parse(&mut buf);
+	assert!(words.skip_word());
+	assert!(words.skip_word());
+	assert_eq!(words.next(), Some("d"));
+}
+
+#[test]
+fn variable_refs()
+{
+	let refs = |s: &str| -> Vec<Vec<String>> {
+		let mut buf = String::from(s);
+		let mut words = parse_posix(&mut buf);
+		let mut rv = Vec::new();
+		while let Some(w) = words.next_word() {
+			rv.push( w.variable_refs().map(|v| v.to_owned()).collect() );
+		}
+		rv
+	};
+	let none: Vec<String> = vec![];
+	assert_eq!(refs("echo $HOME"), [none.clone(), vec!["HOME".to_owned()]]);
+	assert_eq!(refs(r#"echo "${PATH}x""#), [none.clone(), vec!["PATH".to_owned()]]);
+	assert_eq!(refs("echo '$HOME'"), [none.clone(), none.clone()]);
+	assert_eq!(refs(r"$A- $B_1$C ${D $1 $"), [vec!["A".to_owned()], vec!["B_1".to_owned(), "C".to_owned()], none.clone(), none.clone(), none.clone()]);
+	assert_eq!(refs(r#"'$A'"$B"-$D"#), [vec!["B".to_owned(), "D".to_owned()]]);
+
+	// Escaped `$`s aren't references
+	let mut buf = String::from(r#"\$A"\$B"$C"#);
+	let w = super::PosixParser::new().shell_accurate_escapes(true).parse(&mut buf).next_word().unwrap();
+	assert_eq!(w.value(), "$A$B$C");
+	assert_eq!(w.variable_refs().collect::<Vec<_>>(), ["C"]);
+}
+
+#[test]
+fn reusable_parser()
+{
+	const PARSER: super::PosixParser = super::PosixParser::new();
+	let parser = PARSER.on_unterminated(super::Unterminated::Error).max_token_len(8);
+	let mut a = String::from("first 'buffer'");
+	let first = parser.parse(&mut a);
+	for line in &["second \"buf", "third buffer_is_long"]
+	{
+		// Shorter-lived buffer, parsed while the first is still borrowed
+		let mut b = String::from(*line);
+		let mut words = parser.parse(&mut b);
+		assert!(words.next_result().unwrap().is_ok());
+		assert!(words.next_result().unwrap().is_err());
+	}
+	assert_eq!(first.collect::<Vec<_>>(), ["first", "buffer"]);
+}
+
+#[test]
+fn measure()
+{
+	let inputs = [
+		"",
+		"  Hello world  ",
+		r##"Hello world "double quoted (\")" '"single quoted (\')"'  escaped\ string"##,
+		r"a\tb\n 'c\'d' \q",
+		"\"unterminated \\",
+		];
+	for input in &inputs
+	{
+		let m = super::measure(input.as_bytes());
+		let mut buf = String::from(*input);
+		let words: Vec<&str> = parse_posix(&mut buf).collect();
+		assert_eq!(m.word_count, words.len(), "{:?}", input);
+		assert_eq!(m.total_unescaped_len, words.iter().map(|w| w.len()).sum::<usize>(), "{:?}", input);
+		assert_eq!(m.max_word_len, words.iter().map(|w| w.len()).max().unwrap_or(0), "{:?}", input);
+	}
+}
+
+#[test]
+fn cross_quote_literals()
+{
+	let mut s = String::from(r#"'he said "hi"' "it's here" "'"'"'"#);
+	assert_eq!(parse_posix(&mut s).collect::<Vec<_>>(), [r#"he said "hi""#, "it's here", r#"'""#]);
+
+	let mut b = br#"'he said "hi"' "it's here" "'"'"'"#.to_vec();
+	assert_eq!(parse_posix(&mut b[..]).collect::<Vec<_>>(), [&br#"he said "hi""#[..], b"it's here", br#"'""#]);
+}
+
+#[test]
+fn ascii_lowercase()
+{
+	let parser = super::PosixParser::new().ascii_lowercase(true);
+	let mut s = String::from(r#"ECHO Hello "QUOTED Ünïcode" \Escaped\ X"#);
+	assert_eq!(parser.parse(&mut s).collect::<Vec<_>>(), ["echo", "hello", "quoted Ünïcode", "scaped x"]);
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn collect_result()
+{
+	use super::{PosixParser, ParseError};
+	let parser = PosixParser::new().max_token_len(3);
+	let mut s = String::from("abc de");
+	assert_eq!(parser.parse(&mut s).collect_result(), Ok(vec!["abc".to_owned(), "de".to_owned()]));
+	let mut s = String::from("abc defg hi");
+	assert_eq!(parser.parse(&mut s).collect_result(), Err(ParseError::TokenTooLong));
+
+	// The iterator is poisoned after an error, with the rest of the input not consumed
+	let mut s = String::from("abcd hi");
+	let mut iter = parser.parse(&mut s);
+	assert_eq!(iter.next_result(), Some(Err(ParseError::TokenTooLong)));
+	assert_eq!(iter.next_result(), None);
+	assert_eq!(iter.next(), None);
+	assert_eq!(iter.consumed(), 5);
+}
+
+#[test]
+fn last_word()
+{
+	assert_eq!(super::last_word(b"cmd -x -y file.txt  "), Some(&b"file.txt"[..]));
+	assert_eq!(super::last_word(br"cmd 'a b' c\ d"), Some(&br"c\ d"[..]));
+	assert_eq!(super::last_word(b"  "), None);
+
+	let mut s = String::from("cmd -x -y file.txt");
+	let mut words = parse_posix(&mut s);
+	assert_eq!(words.pop_word(), Some("file.txt"));
+	assert_eq!(words.next(), Some("cmd"));
+	assert_eq!(words.pop_word(), Some("-y"));
+	assert_eq!(words.pop_word(), Some("-x"));
+	assert_eq!(words.pop_word(), None);
+	assert_eq!(words.next(), None);
+
+	let mut s = String::from(r#"a "b c" 'd\' e'  "#);
+	let mut words = parse_posix(&mut s);
+	assert_eq!(words.pop_word(), Some("d' e"));
+	assert_eq!(words.collect::<Vec<_>>(), ["a", "b c"]);
+
+	// The last word is before the end of iteration
+	let mut s = String::from("a b END c");
+	let mut words = super::PosixParser::new().stop_token(b"END").parse(&mut s);
+	assert_eq!(words.pop_word(), Some("b"));
+	assert_eq!(words.collect::<Vec<_>>(), ["a"]);
+	let mut s = String::from("a b; c");
+	let mut words = super::PosixParser::new().semicolon(super::Semicolon::End).parse(&mut s);
+	assert_eq!(words.pop_word(), Some("b"));
+	assert_eq!(words.by_ref().collect::<Vec<_>>(), ["a"]);
+	assert_eq!(words.remainder(), "c");
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn word_stream()
+{
+	use std::pin::Pin;
+	use std::task::{Context, Poll};
+	let mut stream = super::WordStream::new(super::PosixParser::new());
+	let mut cx = Context::from_waker(::futures::task::noop_waker_ref());
+	// Minimal executor: poll, feeding another chunk whenever the stream is waiting
+	let mut chunks = ["ec", "ho \"a", " b\"\t", "c\\", " d", " "].iter();
+	let mut words = Vec::new();
+	loop {
+		match Pin::new(&mut stream).poll_next(&mut cx)
+		{
+		Poll::Ready(Some(w)) => words.push(w.unwrap()),
+		Poll::Ready(None) => break,
+		Poll::Pending => match chunks.next()
+			{
+			Some(c) => stream.push_str(c),
+			None => stream.finish(),
+			},
+		}
+	}
+	assert_eq!(words, ["echo", "a b", "c d"]);
+
+	// Errors end the stream
+	let mut stream = super::WordStream::new(super::PosixParser::new().max_token_len(2));
+	stream.push_str("ab abc de");
+	assert_eq!(stream.next_word(), Some(Ok("ab".to_owned())));
+	assert_eq!(stream.next_word(), Some(Err(super::ParseError::TokenTooLong)));
+	assert_eq!(stream.next_word(), None);
+	assert!(stream.is_done());
+
+	// Lots of buffered input, with more pushed part-way through
+	let mut stream = super::WordStream::new(super::PosixParser::new());
+	let input: Vec<String> = (0 .. 1000).map(|i| format!("w{}", i)).collect();
+	stream.push_str(&input[..600].join(" "));
+	stream.push_str(" ");
+	let mut words = Vec::new();
+	for _ in 0 .. 300 {
+		words.push(stream.next_word().unwrap().unwrap());
+	}
+	stream.push_str(&input[600..].join(" "));
+	stream.finish();
+	while let Some(w) = stream.next_word() {
+		words.push(w.unwrap());
+	}
+	assert_eq!(words, input);
+	assert!(stream.is_done());
+}
+
+/// Feeding the input in pieces gives the same words as parsing it all at once, for options that depend on earlier
+/// words
+#[test]
+#[cfg(feature="alloc")]
+fn word_stream_state()
+{
+	use super::{PosixParser, BareCommand, Semicolon};
+	let cases = [
+		(PosixParser::new().command_separator(b':'), "run:a b:c "),
+		(PosixParser::new().comments(true), "a # b c\nd"),
+		(PosixParser::new().comments(true), "a b#c d"),
+		(PosixParser::new().stop_token(b"END"), "a ENDx END b"),
+		(PosixParser::new().semicolon(Semicolon::End), "echo a; echo b"),
+		(PosixParser::new().semicolon(Semicolon::Marker).bare_command(BareCommand::Literal), "'a' b;'c' d"),
+		(PosixParser::new().eof_is_final(false), "foo ba"),
+		];
+	for &(opts, input) in &cases
+	{
+		let mut buf = String::from(input);
+		let expected: Vec<String> = opts.eof_is_final(true).parse(&mut buf).map(|w| w.to_owned()).collect();
+		for chunk_len in 1 ..= input.len()
+		{
+			let mut stream = super::WordStream::new(opts);
+			let mut words = Vec::new();
+			for chunk in input.as_bytes().chunks(chunk_len) {
+				stream.push_str(::std::str::from_utf8(chunk).unwrap());
+				while let Some(w) = stream.next_word() {
+					words.push(w.unwrap());
+				}
+			}
+			stream.finish();
+			while let Some(w) = stream.next_word() {
+				words.push(w.unwrap());
+			}
+			assert_eq!(words, expected, "{:?} in chunks of {}", input, chunk_len);
+			assert!(stream.is_done(), "{:?} in chunks of {}", input, chunk_len);
+		}
+	}
+}
+
+#[test]
+#[cfg(feature="futures")]
+fn word_stream_futures()
+{
+	use futures::StreamExt;
+	let mut stream = super::WordStream::new(super::PosixParser::new().comments(true));
+	stream.push_str("ls -l 'a b' # list\nignored");
+	stream.finish();
+	let words = ::futures::executor::block_on(stream.collect::<Vec<_>>());
+	assert_eq!(words, [Ok("ls".to_owned()), Ok("-l".to_owned()), Ok("a b".to_owned())]);
+}
+
+#[test]
+fn tabs()
+{
+	// Unescaped tabs (and runs of them) separate
+	let mut s = String::from("a\tb\t\t c \t");
+	assert_eq!(parse_posix(&mut s).collect::<Vec<_>>(), ["a", "b", "c"]);
+	// Escaped (`\t` or backslash-tab) and quoted tabs don't
+	let mut s = String::from("a\\tb \"a\\tb\" 'a\tb' a\\\tb");
+	assert_eq!(parse_posix(&mut s).collect::<Vec<_>>(), ["a\tb"; 4]);
+}
+
+#[test]
+fn unescape_all()
+{
+	let mut s = String::from(r"a\ b\ c");
+	assert_eq!(super::unescape_all(&mut s), "a b c");
+	let mut s = String::from("  'a  b' \"c\\td\"\t");
+	assert_eq!(super::unescape_all(&mut s), "  a  b c\td\t");
+	let mut b = br#"x\"y"#.to_vec();
+	assert_eq!(super::unescape_all(&mut b[..]), br#"x"y"#);
+
+	let mut b = *br"a\ b";
+	assert_eq!(super::unescape_in_place(&mut b), 3);
+	assert_eq!(&b[..3], b"a b");
+	let mut b = *br#"'it'\''s' "\"x\"""#;
+	let len = super::unescape_in_place(&mut b);
+	assert_eq!(&b[..len], br#"it's "x""#);
+	assert_eq!(super::unescape_in_place(&mut []), 0);
+}
+
+#[test]
+fn trailing_backslash()
+{
+	use super::{PosixParser, Unterminated, ParseError};
+	let cases: [(&str, &str, ParseError); 4] = [
+		("\"abc\\", "abc", ParseError::UnterminatedEscape),
+		("'abc\\", "abc", ParseError::UnterminatedEscape),
+		("abc\\", "abc", ParseError::UnterminatedEscape),
+		("\"abc", "abc", ParseError::UnterminatedQuote),
+		];
+	for &(input, lenient, strict) in &cases
+	{
+		let mut s = String::from(input);
+		let mut iter = PosixParser::new().parse(&mut s);
+		assert_eq!(iter.next_result(), Some(Ok(lenient)), "{:?}", input);
+		assert_eq!(iter.next_result(), None);
+
+		let mut s = String::from(input);
+		let mut iter = PosixParser::new().on_unterminated(Unterminated::Error).parse(&mut s);
+		assert_eq!(iter.next_result(), Some(Err(strict)), "{:?}", input);
+		assert_eq!(iter.next_result(), None);
+
+		let mut s = String::from(input);
+		let mut iter = PosixParser::new().on_unterminated(Unterminated::Drop).parse(&mut s);
+		assert_eq!(iter.next_result(), None, "{:?}", input);
+	}
+}
+
+#[test]
+fn double_quote_as_literal()
+{
+	let input = r#""a""b" "" "x""" a""b"#;
+	let mut s = String::from(input);
+	let csv = super::PosixParser::new().double_quote_as_literal(true);
+	assert_eq!(csv.parse(&mut s).collect::<Vec<_>>(), [r#"a"b"#, "", r#"x""#, "ab"]);
+	let mut s = String::from(input);
+	let mut words = csv.parse(&mut s);
+	assert!(words.skip_word());
+	assert_eq!(words.next(), Some(""));
+	// Default: close and re-open
+	let mut s = String::from(input);
+	assert_eq!(parse_posix(&mut s).collect::<Vec<_>>(), ["ab", "", "x", "ab"]);
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn traced()
+{
+	use super::TraceEvent;
+	let mut s = String::from(r#" 'a\'b' "c\q"d "#);
+	let mut trace = Vec::new();
+	let words: Vec<_> = crate::parse_posix_traced(&mut s, &mut trace).collect();
+	assert_eq!(words, ["a'b", "cd"]);
+	assert_eq!(trace, [
+		TraceEvent::EnterQuote { pos: 1, quote: b'\'' },
+		TraceEvent::Escape { pos: 4, input: b'\'', output: Some(b'\'') },
+		TraceEvent::ExitQuote { pos: 6 },
+		TraceEvent::TokenBoundary { pos: 7 },
+		TraceEvent::EnterQuote { pos: 8, quote: b'"' },
+		TraceEvent::Escape { pos: 11, input: b'q', output: None },
+		TraceEvent::ExitQuote { pos: 12 },
+		TraceEvent::TokenBoundary { pos: 14 },
+		]);
+}
+
+#[test]
+fn traced_with_options()
+{
+	use super::TraceEvent;
+	// The trace follows the parser's options: a soft separator ends the word, and quotes in comments are ignored
+	let parser = super::PosixParser::new().soft_separator(b',').comments(true);
+	let mut s = String::from("a,'b' # 'c'");
+	let mut trace = Vec::new();
+	let words: Vec<_> = parser.parse_traced(&mut s, &mut trace).collect();
+	assert_eq!(words, ["a", "b"]);
+	assert_eq!(trace, [
+		TraceEvent::TokenBoundary { pos: 1 },
+		TraceEvent::EnterQuote { pos: 2, quote: b'\'' },
+		TraceEvent::ExitQuote { pos: 4 },
+		TraceEvent::TokenBoundary { pos: 5 },
+		]);
+}
+
+#[test]
+fn stop_token()
+{
+	let parser = super::PosixParser::new().stop_token(b";;");
+	// Quoted sentinels don't stop
+	let mut s = String::from("a ';;' \";;\" b ;;  c d ;; e");
+	let mut words = parser.parse(&mut s);
+	assert_eq!(words.by_ref().collect::<Vec<_>>(), ["a", ";;", ";;", "b"]);
+	assert!(!words.skip_word());
+	// Resume with the remainder
+	let rest = words.into_remainder();
+	assert_eq!(rest, "c d ;; e");
+	let mut words = parser.parse(rest);
+	assert!(words.skip_word());
+	assert_eq!(words.next(), Some("d"));
+	assert_eq!(words.next(), None);
+	assert_eq!(words.remainder(), "e");
+
+	// Sentinel at the end
+	let mut s = String::from("a END");
+	let mut words = super::PosixParser::new().stop_token(b"END").parse(&mut s);
+	assert_eq!(words.by_ref().collect::<Vec<_>>(), ["a"]);
+	assert_eq!(words.remainder(), "");
+
+	// Sentinel read at runtime (e.g. a heredoc delimiter), and dropped before parsing
+	let parser = {
+		let delim = String::from("EOF_1234");
+		super::PosixParser::new().stop_token(delim.as_bytes())
+	};
+	let mut s = String::from("x y EOF_1234 z");
+	let mut words = parser.parse(&mut s);
+	assert_eq!(words.by_ref().collect::<Vec<_>>(), ["x", "y"]);
+	assert_eq!(words.remainder(), "z");
+}
+
+#[test]
+#[should_panic]
+fn stop_token_too_long()
+{
+	let _ = super::PosixParser::new().stop_token(&[b'x'; super::MAX_STOP_TOKEN_LEN + 1]);
+}
+
+#[test]
+fn background()
+{
+	use super::{tokens, Tok, parse_until_operator, Operator};
+	let mut s = String::from("sleep 10 &");
+	assert_eq!(tokens(&mut s).collect::<Vec<_>>(), [Tok::Word("sleep"), Tok::Word("10"), Tok::Background]);
+	let mut s = String::from("a && b&c '&'");
+	assert_eq!(tokens(&mut s).collect::<Vec<_>>(), [Tok::Word("a"), Tok::And, Tok::Word("b"), Tok::Background, Tok::Word("c"), Tok::Word("&")]);
+	let mut s = String::from("cmd &> log");
+	assert_eq!(tokens(&mut s).collect::<Vec<_>>(), [Tok::Word("cmd"), Tok::RedirectBoth, Tok::Word("log")]);
+
+	let mut s = String::from("sleep 10 & echo");
+	let (words, op, rest) = parse_until_operator(&mut s);
+	assert_eq!(words.collect::<Vec<_>>(), ["sleep", "10"]);
+	assert_eq!(op, Some(Operator::Background));
+	assert_eq!(rest, " echo");
+	let mut s = String::from("cmd &> log");
+	let (words, op, _) = parse_until_operator(&mut s);
+	assert_eq!(words.collect::<Vec<_>>(), ["cmd", "&>", "log"]);
+	assert_eq!(op, None);
+}
+
+#[test]
+fn is_posix_special()
+{
+	use super::is_posix_special;
+	for &b in b" \t\r\n\\'\"" {
+		assert!(is_posix_special(b), "{:?}", b as char);
+	}
+	for &b in b"aZ09-_/.|&;$`*?~#\x00\x7F\x80\xFF" {
+		assert!(!is_posix_special(b), "{:?}", b);
+	}
+	// Matches the state machine: every other byte is copied verbatim
+	for b in 0 ..= 255u8 {
+		let (mode, out) = super::step(super::PosixEscapeMode::Outer, b);
+		assert_eq!(mode == super::PosixEscapeMode::Outer && out == Some(b) && !super::is_separator(b), !is_posix_special(b), "{:?}", b);
+	}
+}
+
+#[test]
+#[cfg(feature="args")]
+fn parse_args()
+{
+	use super::{parse_args, Arg};
+	let mut s = String::from("run --name=foo -v pos1 -- pos2");
+	assert_eq!(parse_args(&mut s).collect::<Vec<_>>(), [
+		Arg::Positional("run"), Arg::KeyValue("name", "foo"), Arg::Flag("v"), Arg::Positional("pos1"), Arg::Positional("pos2"),
+		]);
+	// Quoted/escaped dashes are positional, as is a lone `-`
+	let mut s = String::from(r#"'--x' ""-y "-z" - --k="a b" --="""#);
+	assert_eq!(parse_args(&mut s).collect::<Vec<_>>(), [
+		Arg::Positional("--x"), Arg::Positional("-y"), Arg::Positional("-z"), Arg::Positional("-"), Arg::KeyValue("k", "a b"), Arg::KeyValue("", ""),
+		]);
+	// Value flags take the next word (quoted, or even after `--`), other flags don't
+	let mut s = String::from(r#"--out "a b" -o -- --name x -- --out y --out"#);
+	assert_eq!(parse_args(&mut s).value_keys(&["out", "o"]).collect::<Vec<_>>(), [
+		Arg::KeyValue("out", "a b"), Arg::KeyValue("o", "--"), Arg::Flag("name"), Arg::Positional("x"), Arg::Positional("--out"), Arg::Positional("y"), Arg::Positional("--out"),
+		]);
+	let mut s = String::from("-v --out");
+	assert_eq!(parse_args(&mut s).value_keys(&["out"]).collect::<Vec<_>>(), [Arg::Flag("v"), Arg::Flag("out")]);
+}
+
+/// Multi-byte characters survive escaping and quoting, and the whole buffer stays valid UTF-8
+#[test]
+fn multibyte_intact()
+{
+	let input = "caf\u{e9} \\\u{e9}\\\u{1F600} '\u{1F600}\\\u{e9}' \"\u{e9}\\\u{1F600}\"\u{3000}x \u{2018}\u{201C}";
+	let expected = ["caf\u{e9}", "\u{e9}\u{1F600}", "\u{1F600}\u{e9}", "\u{e9}\u{1F600}\u{3000}x", "\u{2018}\u{201C}"];
+	let parsers = [
+		super::PosixParser::new(),
+		super::PosixParser::new().shell_accurate_escapes(true),
+		super::PosixParser::new().keep_enclosing_quotes(true).ascii_lowercase(true),
+		];
+	for (i, parser) in parsers.iter().enumerate()
+	{
+		let mut s = String::from(input);
+		let words: Vec<String> = parser.parse(&mut s).map(|w| w.to_owned()).collect();
+		assert_eq!(words.len(), expected.len());
+		if i == 0 {
+			assert_eq!(words, expected);
+		}
+		assert!(::std::str::from_utf8(s.as_bytes()).is_ok());
+	}
+}
+
+#[test]
+fn single_quote_escapes()
+{
+	let input = r#"'a\nb' 'c\td\r' '\'\\\"' 'x\q'"#;
+	let mut s = String::from(input);
+	let words: Vec<_> = super::PosixParser::new().single_quote_escapes(true).parse(&mut s).collect();
+	assert_eq!(words, ["a\nb", "c\td\r", "'\\\"", "x"]);
+	// Default: only `\'` and `\\`
+	let mut s = String::from(input);
+	assert_eq!(parse_posix(&mut s).collect::<Vec<_>>(), ["ab", "cd", "'\\", "x"]);
+}
+
+#[test]
+fn is_balanced()
+{
+	use super::is_balanced;
+	assert!(is_balanced(b""));
+	assert!(is_balanced(br#"a "b" 'c'"#));
+	assert!(is_balanced(br#"'a"' "b'" \' \""#));
+	assert!(!is_balanced(b"'a"));
+	assert!(!is_balanced(br#""a"#));
+	assert!(!is_balanced(br"a\"));
+	assert!(!is_balanced(br#""a\""#));
+}
+
+#[test]
+fn empty_quoted()
+{
+	let words = |s: &str| -> Vec<String> {
+		let mut buf = String::from(s);
+		parse_posix(&mut buf).map(|w| w.to_owned()).collect()
+	};
+	assert_eq!(words("''"), [""]);
+	assert_eq!(words("\"\""), [""]);
+	assert_eq!(words("'' ''"), ["", ""]);
+	assert_eq!(words("  ''\t\"\"  "), ["", ""]);
+	assert_eq!(words("a '' b"), ["a", "", "b"]);
+	assert_eq!(words("''\"\""), [""]);
+	// Plain whitespace has no words
+	assert!(words(" \t ").is_empty());
+
+	let mut buf = String::from("a '' ");
+	let mut iter = parse_posix(&mut buf);
+	assert_eq!(iter.pop_word(), Some(""));
+	assert_eq!(iter.pop_word(), Some("a"));
+	assert_eq!(iter.pop_word(), None);
+}
+
+#[test]
+fn truncate_words()
+{
+	use super::truncate_words;
+	let line = br#"foo "bar baz" qux"#;
+	assert_eq!(truncate_words(line, 0), 0);
+	assert_eq!(truncate_words(line, 2), 0);
+	for max in 3 .. 13 {
+		assert_eq!(truncate_words(line, max), 3);
+	}
+	assert_eq!(truncate_words(line, 13), 13);
+	assert_eq!(truncate_words(line, 100), line.len());
+	assert_eq!(truncate_words(br"a\ b c", 4), 4);
+	assert_eq!(truncate_words(br"a\ b c", 3), 0);
+	// Incomplete words are never included
+	assert_eq!(truncate_words(b"foo \"bar", 100), 4);
+	assert_eq!(truncate_words(b"foo \"bar", 5), 3);
+	assert_eq!(truncate_words(br"foo bar\", 100), 4);
+	assert_eq!(truncate_words(b"'foo", 100), 0);
+}
+
+#[test]
+fn trailing_escaped_space()
+{
+	let words = |s: &str| -> Vec<String> {
+		let mut buf = String::from(s);
+		parse_posix(&mut buf).map(|w| w.to_owned()).collect()
+	};
+	assert_eq!(words("a\\ "), ["a "]);
+	assert_eq!(words("a \\ "), ["a", " "]);
+	assert_eq!(words("a\\  \\ \\  "), ["a ", "  "]);
+	assert_eq!(words("a\\\t\t"), ["a\t"]);
+	assert_eq!(words("\\ "), [" "]);
+}
+
+#[test]
+fn next_with_opts()
+{
+	use super::PosixParser;
+	let mut s = String::from(r#"cmd --raw "a b" \n 'c"#);
+	let mut words = parse_posix(&mut s);
+	let mut out = Vec::new();
+	let mut opts = PosixParser::new();
+	while let Some(w) = words.next_with_opts(&opts) {
+		if w == "--raw" {
+			opts = opts.raw(true);
+		}
+		out.push(w);
+	}
+	assert_eq!(out, ["cmd", "--raw", "\"a", "b\"", r"\n", "'c"]);
+}
+
+#[test]
+fn substitutions()
+{
+	let parser = super::PosixParser::new().substitutions(true);
+	let words = |s: &str| -> Vec<String> {
+		let mut buf = String::from(s);
+		parser.parse(&mut buf).map(|w| w.to_owned()).collect()
+	};
+	assert_eq!(words("echo $(( 1 + 2 )) done"), ["echo", "$(( 1 + 2 ))", "done"]);
+	assert_eq!(words("a$(b 'c)' \\d)e f"), ["a$(b 'c)' \\d)e", "f"]);
+	assert_eq!(words("$(( (1+2) * 3 ))"), ["$(( (1+2) * 3 ))"]);
+	assert_eq!(words("\"$(a \"b\")\" '$(a'b)"), ["$(a \"b\")", "$(ab)"]);
+	// Unterminated substitutions aren't special
+	assert_eq!(words("$(a b"), ["$(a", "b"]);
+	// Skipping uses the same boundaries
+	let mut buf = String::from("$(a b) c");
+	let mut iter = parser.parse(&mut buf);
+	assert!(iter.skip_word());
+	assert_eq!(iter.next(), Some("c"));
+	// Disabled by default
+	let mut buf = String::from("$(a b)");
+	assert_eq!(parse_posix(&mut buf).collect::<Vec<_>>(), ["$(a", "b)"]);
+}
+
+#[test]
+#[cfg(feature="std")]
+fn expand_globs()
+{
+	let mut buf = String::from("ls -l *.txt '*'.txt b[12] \"*.txt\" *.none");
+	let mut calls = Vec::new();
+	let words: Vec<_> = parse_posix(&mut buf).expand_globs(|pat| {
+		calls.push(pat.to_owned());
+		match pat
+		{
+		"*.txt" => Some(vec!["a.txt".to_owned(), "b.txt".to_owned()]),
+		_ => None,
+		}
+		}).collect();
+	assert_eq!(words, ["ls", "-l", "a.txt", "b.txt", "*.txt", "b[12]", "*.txt", "*.none"]);
+	assert_eq!(calls, ["*.txt", "b[12]", "*.none"]);
+}
+
+#[test]
+fn last_token_copied()
+{
+	let mut buf = String::from("plain 'quoted' a\\ b  trailing");
+	let mut words = parse_posix(&mut buf);
+	assert!(!words.last_token_copied());
+	assert_eq!(words.next(), Some("plain"));
+	assert!(!words.last_token_copied());
+	assert_eq!(words.next(), Some("quoted"));
+	assert!(words.last_token_copied());
+	assert_eq!(words.next(), Some("a b"));
+	assert!(words.last_token_copied());
+	// Separators before a word don't require a copy
+	assert_eq!(words.next(), Some("trailing"));
+	assert!(!words.last_token_copied());
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn retain_words()
+{
+	let mut s = String::from("run -v -x file");
+	super::retain_words(&mut s, |w| !w.starts_with('-'));
+	assert_eq!(s, "run file");
+	// Kept words are re-quoted as needed, and parse back to the same words
+	let mut s = String::from(r#"a "b c" '' d\'e -q"#);
+	super::retain_words(&mut s, |w| w != "-q");
+	assert_eq!(s, r"a 'b c' '' 'd'\''e'");
+	assert_eq!(parse_posix(&mut s).to_vec(), ["a", "b c", "", "d'e"]);
+	let mut s = String::from("-a -b");
+	super::retain_words(&mut s, |w| !w.starts_with('-'));
+	assert_eq!(s, "");
+}
+
+#[test]
+fn parse_lines()
+{
+	let mut buf = String::from("echo 'first\nline' x\nsecond \\\nline\n\nthird");
+	let mut lines = super::parse_lines(&mut buf);
+	assert_eq!(lines.next().unwrap().collect::<Vec<_>>(), ["echo", "first\nline", "x"]);
+	assert_eq!(lines.next().unwrap().collect::<Vec<_>>(), ["second", "\nline"]);
+	// Blank lines are yielded (with no words)
+	assert_eq!(lines.next().unwrap().count(), 0);
+	assert_eq!(lines.next().unwrap().collect::<Vec<_>>(), ["third"]);
+	assert!(lines.next().is_none());
+}
+
+#[test]
+fn split_once()
+{
+	let mut buf = String::from("cmd the rest here");
+	assert_eq!(super::split_once(&mut buf[..]), ("cmd", Some("the rest here")));
+	let mut buf = String::from("  x  ");
+	assert_eq!(super::split_once(&mut buf[..]), ("x", None));
+	// The rest isn't unescaped
+	let mut buf = String::from("'a b'\t \"c d\" e\\ f");
+	assert_eq!(super::split_once(&mut buf[..]), ("a b", Some("\"c d\" e\\ f")));
+	let mut buf = String::from("   ");
+	assert_eq!(super::split_once(&mut buf[..]), ("", None));
+	let mut buf = *b"a b";
+	assert_eq!(super::split_once(&mut buf[..]), (&b"a"[..], Some(&b"b"[..])));
+}
+
+#[test]
+fn ansi_escapes()
+{
+	// ESC isn't special, so the sequence stays within the word (even when quoted)
+	let mut buf = String::from("ls \x1b[1;32mgreen\x1b[0m '\x1b[31mred file' plain\x1b");
+	let mut words = parse_posix(&mut buf);
+	assert!(!words.next_word().unwrap().contains_ansi());
+	let w = words.next_word().unwrap();
+	assert_eq!(w.value(), "\x1b[1;32mgreen\x1b[0m");
+	assert!(w.contains_ansi());
+	let w = words.next_word().unwrap();
+	assert_eq!(w.value(), "\x1b[31mred file");
+	assert!(w.contains_ansi());
+	let w = words.next_word().unwrap();
+	assert_eq!(w.value(), "plain\x1b");
+	assert!(!w.contains_ansi());
+}
+
+#[test]
+fn command_separator()
+{
+	let parser = super::PosixParser::new().command_separator(b':');
+	let mut buf = String::from("run:a b c");
+	assert_eq!(parser.parse(&mut buf).collect::<Vec<_>>(), ["run", "a", "b", "c"]);
+	// Quoted separators don't count, and it can be followed by whitespace
+	let mut buf = String::from("'a:b'\"x:y\"c: d:e");
+	let mut words = parser.parse(&mut buf);
+	let w = words.next_word().unwrap();
+	assert_eq!(w.value(), "a:bx:yc");
+	assert_eq!(w.trailing_separator(), Some(b':'));
+	assert_eq!(words.collect::<Vec<_>>(), ["d:e"]);
+	// Only the first word
+	let mut buf = String::from("run a:b");
+	assert_eq!(parser.parse(&mut buf).collect::<Vec<_>>(), ["run", "a:b"]);
+	let mut buf = String::from("run:a:b");
+	let mut words = parser.parse(&mut buf);
+	assert!(words.skip_word());
+	assert_eq!(words.collect::<Vec<_>>(), ["a:b"]);
+	let mut buf = String::from("run:a:b");
+	assert_eq!(parser.parse(&mut buf).pop_word(), Some("a:b"));
+	let mut buf = String::from("  run:");
+	assert_eq!(parser.parse(&mut buf).pop_word(), Some("run"));
+}
+
+#[test]
+#[cfg(feature="std")]
+fn to_argv()
+{
+	use std::ffi::CStr;
+	let (args, argv) = super::to_argv("ls -la 'my dir'").unwrap();
+	assert_eq!(args.len(), 3);
+	assert_eq!(argv.len(), 4);
+	for (a, &p) in args.iter().zip(&argv) {
+		assert_eq!(a.as_ptr(), p);
+	}
+	// SAFE: The pointers are to the live `CString`s
+	let words: Vec<_> = argv[..3].iter().map(|&p| unsafe { CStr::from_ptr(p) }.to_str().unwrap()).collect();
+	assert_eq!(words, ["ls", "-la", "my dir"]);
+	assert!(argv[3].is_null());
+	// Interior NULs can't be represented
+	assert!(super::to_argv("a b\0c").is_err());
+	assert_eq!(super::to_argv("").unwrap().1, [std::ptr::null()]);
+}
+
+#[test]
+fn backslash_newline_separates()
+{
+	let parser = super::PosixParser::new().backslash_newline_separates(true);
+	let mut buf = String::from("tool \\\n\t--input=a\\\n\t--output 'b c' \\\n\t-v\\\n");
+	assert_eq!(parser.parse(&mut buf).collect::<Vec<_>>(), ["tool", "--input=a", "--output", "b c", "-v"]);
+	// Takes precedence over line continuations
+	let mut buf = String::from("a\\\nb");
+	assert_eq!(parser.bash_compatible_escapes(true).parse(&mut buf).collect::<Vec<_>>(), ["a", "b"]);
+	// Quoted ones don't separate (the normal escaping applies)
+	let mut buf = String::from("\"a\\\nb\" 'c\\\nd'");
+	assert_eq!(parser.parse(&mut buf).collect::<Vec<_>>(), ["ab", "cd"]);
+	// Without the option, it's an escaped newline
+	let mut buf = String::from("a\\\nb");
+	assert_eq!(parse_posix(&mut buf).collect::<Vec<_>>(), ["a\nb"]);
+}
+
+#[test]
+fn trim_tokens()
+{
+	let parser = super::PosixParser::new().trim_tokens(true);
+	let mut buf = String::from("\" a b \" '\t\tc' \\ d\\  \"  \"");
+	let mut words = parser.parse(&mut buf);
+	assert_eq!(words.next(), Some("a b"));
+	assert_eq!(words.next(), Some("c"));
+	assert_eq!(words.next(), Some("d"));
+	// Whitespace-only words become empty (instead of being removed)
+	assert_eq!(words.next(), Some(""));
+	assert_eq!(words.next(), None);
+	let mut buf = String::from("\" a b \"");
+	assert_eq!(parse_posix(&mut buf).next(), Some(" a b "));
+}
+
+#[test]
+fn fixed_word_stream()
+{
+	let mut storage = [0; 8];
+	let mut stream = super::FixedWordStream::new(&mut storage, Default::default());
+	// A word split across two feeds
+	assert_eq!(stream.feed(b"ab 'c d"), 7);
+	assert_eq!(stream.next_word(), Some(Ok(&b"ab"[..])));
+	assert_eq!(stream.next_word(), None);
+	// Only part of the input fits
+	let rest = b"e' f\\ g h";
+	assert_eq!(stream.feed(rest), 4);
+	assert_eq!(stream.next_word(), Some(Ok(&b"c de"[..])));
+	assert_eq!(stream.feed(&rest[4..]), 5);
+	assert_eq!(stream.next_word(), Some(Ok(&b"f g"[..])));
+	assert_eq!(stream.next_word(), None);
+	stream.finish();
+	assert_eq!(stream.next_word(), Some(Ok(&b"h"[..])));
+	assert_eq!(stream.next_word(), None);
+	assert!(stream.is_done());
+	
+	// A word longer than the buffer
+	let mut storage = [0; 4];
+	let mut stream = super::FixedWordStream::new(&mut storage, Default::default());
+	assert_eq!(stream.feed(b"a abcdef"), 4);
+	assert_eq!(stream.next_word(), Some(Ok(&b"a"[..])));
+	assert_eq!(stream.feed(b"cdef"), 2);
+	assert_eq!(stream.next_word(), Some(Err(super::ParseError::TokenTooLong)));
+	assert_eq!(stream.feed(b"ef"), 0);
+	assert_eq!(stream.next_word(), None);
+	assert!(stream.is_done());
+
+	// Separators after a word don't take up space
+	let mut storage = [0; 8];
+	let mut stream = super::FixedWordStream::new(&mut storage, Default::default());
+	assert_eq!(stream.feed(b"ab      "), 8);
+	assert_eq!(stream.next_word(), Some(Ok(&b"ab"[..])));
+	assert_eq!(stream.feed(b"cdef"), 4);
+	stream.finish();
+	assert_eq!(stream.next_word(), Some(Ok(&b"cdef"[..])));
+	assert!(stream.is_done());
+}
+
+#[test]
+fn fixed_word_stream_state()
+{
+	let mut storage = [0; 8];
+	// The stop token ends the stream, even if more input follows
+	let opts = super::PosixParser::new().stop_token(b"END");
+	let mut stream = super::FixedWordStream::new(&mut storage, opts);
+	assert_eq!(stream.feed(b"a END b "), 8);
+	assert_eq!(stream.next_word(), Some(Ok(&b"a"[..])));
+	assert_eq!(stream.next_word(), None);
+	assert!(stream.is_done());
+	assert_eq!(stream.next_word(), None);
+
+	// As does a comment
+	let mut stream = super::FixedWordStream::new(&mut storage, super::PosixParser::new().comments(true));
+	assert_eq!(stream.feed(b"a #'b "), 6);
+	assert_eq!(stream.next_word(), Some(Ok(&b"a"[..])));
+	assert_eq!(stream.next_word(), None);
+	assert!(!stream.is_done());
+	assert_eq!(stream.feed(b"c\nd"), 3);
+	assert_eq!(stream.next_word(), None);
+	assert!(stream.is_done());
+
+	// And a terminating semicolon
+	let mut stream = super::FixedWordStream::new(&mut storage, super::PosixParser::new().semicolon(super::Semicolon::End));
+	assert_eq!(stream.feed(b"a; b"), 4);
+	assert_eq!(stream.next_word(), Some(Ok(&b"a"[..])));
+	assert_eq!(stream.next_word(), None);
+	assert!(stream.is_done());
+
+	// The command separator only applies to the first word
+	let mut stream = super::FixedWordStream::new(&mut storage, super::PosixParser::new().command_separator(b':'));
+	assert_eq!(stream.feed(b"a:b:c "), 6);
+	assert_eq!(stream.next_word(), Some(Ok(&b"a"[..])));
+	assert_eq!(stream.next_word(), Some(Ok(&b"b:c"[..])));
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn clone_remaining()
+{
+	let mut buf = String::from("cmd --name='A B' x\\ y END z");
+	let mut words = parse_posix(&mut buf);
+	assert_eq!(words.next(), Some("cmd"));
+	let mut snapshot = words.clone_remaining();
+	// Parse the snapshot with different options
+	let mut other = super::PosixParser::new().keep_enclosing_quotes(true).stop_token(b"END").parse(&mut snapshot[..]);
+	assert_eq!(other.by_ref().collect::<Vec<_>>(), [&b"--name=A B"[..], b"x y"]);
+	assert_eq!(other.remainder(), b"z");
+	// The original continues unchanged
+	assert_eq!(words.collect::<Vec<_>>(), ["--name=A B", "x y", "END", "z"]);
+}
+
+#[test]
+fn quote_style()
+{
+	use super::QuoteStyle;
+	let mut buf = String::from(r#"abc 'abc' "abc" a'b'"c" 'a''b' a\ b '' \'"#);
+	let mut words = parse_posix(&mut buf);
+	let mut styles = Vec::new();
+	while let Some(w) = words.next_word() {
+		styles.push(w.quote_style());
+	}
+	assert_eq!(styles, [
+		QuoteStyle::None, QuoteStyle::Single, QuoteStyle::Double, QuoteStyle::Mixed,
+		QuoteStyle::Single, QuoteStyle::None, QuoteStyle::Single, QuoteStyle::None,
+		]);
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn quote_double()
+{
+	for &w in &["", "plain", "a b", "\"quoted\"", "back\\slash", "it's", "tab\tnew\nline", "\\\"", "ünï"] {
+		let mut quoted = super::quote_double(w);
+		assert_eq!(parse_posix(&mut quoted).collect::<Vec<_>>(), [w], "{:?}", w);
+	}
+	// `$` and `` ` `` are only escapes with bash-style double quotes
+	let parser = super::PosixParser::new().bash_double_quotes(true);
+	for &w in &["$HOME", "`cmd`", "a $b \\$c \"`\""] {
+		let mut quoted = super::quote_double(w);
+		assert_eq!(parser.parse(&mut quoted).collect::<Vec<_>>(), [w], "{:?}", w);
+	}
+	assert_eq!(super::quote_double("a\"b"), "\"a\\\"b\"");
+}
+
+#[test]
+fn parse_fixed()
+{
+	use super::{parse_fixed, ArityError};
+	let mut buf = String::from("move 'a b' c");
+	assert_eq!(parse_fixed(&mut buf[..]), Ok(["move", "a b", "c"]));
+	let mut buf = String::from("move a");
+	assert_eq!(parse_fixed::<_, 3>(&mut buf[..]), Err(ArityError { expected: 3, actual: 2 }));
+	let mut buf = String::from("move a b c d");
+	assert_eq!(parse_fixed::<_, 3>(&mut buf[..]), Err(ArityError { expected: 3, actual: 5 }));
+	let mut buf = String::from("  ");
+	assert_eq!(parse_fixed::<str, 0>(&mut buf[..]), Ok([]));
+}
+
+#[test]
+fn validate()
+{
+	use super::{validate, ParseError};
+	let cases: &[(&str, Result<(), ParseError>)] = &[
+		("", Ok(())),
+		("  ", Ok(())),
+		(r#"cmd 'a b' "c\"d" e\ f"#, Ok(())),
+		("cmd 'a b", Err(ParseError::UnterminatedQuote)),
+		("cmd \"a b", Err(ParseError::UnterminatedQuote)),
+		("cmd a\\", Err(ParseError::UnterminatedEscape)),
+		("cmd \"a\\", Err(ParseError::UnterminatedEscape)),
+		];
+	for &(input, expected) in cases {
+		assert_eq!(validate(input.as_bytes()), expected, "{:?}", input);
+		// Matches the (destructive) fallible parser
+		let mut buf = String::from(input);
+		let mut words = super::PosixParser::new().on_unterminated(super::Unterminated::Error).parse(&mut buf);
+		let parsed = loop {
+			match words.next_result()
+			{
+			None => break Ok(()),
+			Some(Err(e)) => break Err(e),
+			Some(Ok(_)) => {},
+			}
+		};
+		assert_eq!(parsed, expected, "{:?}", input);
+	}
+}
+
+/// Validation with a configured parser reports what that parser would
+#[test]
+fn validate_configured()
+{
+	use super::{PosixParser, ParseError, Unterminated, InvalidEscape};
+	let strict = PosixParser::new().on_unterminated(Unterminated::Error);
+	assert_eq!(PosixParser::new().validate(b"a 'b"), Ok(()));
+	assert_eq!(strict.validate(b"a 'b"), Err(ParseError::UnterminatedQuote));
+	// Nothing after the stop token is parsed
+	assert_eq!(strict.stop_token(b"END").validate(b"a END 'b"), Ok(()));
+	assert_eq!(PosixParser::new().max_token_len(3).validate(b"abc abcd"), Err(ParseError::TokenTooLong));
+	let recover = PosixParser::new().on_invalid_escape(InvalidEscape::Recover);
+	assert_eq!(recover.validate(br"a \q"), Err(ParseError::InvalidEscape));
+	assert_eq!(recover.validate(br"a \\q"), Ok(()));
+	// With bash's double quote rules, the backslash in `"\q"` is kept (so isn't an invalid escape)
+	assert_eq!(recover.validate(br#""\q""#), Err(ParseError::InvalidEscape));
+	assert_eq!(recover.bash_double_quotes(true).validate(br#""\q""#), Ok(()));
+}
+
+#[test]
+#[cfg(feature="std")]
+fn to_os_args()
+{
+	use std::ffi::OsString;
+	let args = super::to_os_args("--verbose input.txt", None);
+	assert_eq!(args, [OsString::from("--verbose"), OsString::from("input.txt")]);
+	let args = super::to_os_args("  ", Some("prog"));
+	assert_eq!(args, [OsString::from("prog")]);
+	let args = super::to_os_args("-o 'out file'", Some("my prog"));
+	assert_eq!(args, ["my prog", "-o", "out file"]);
+}
+
+#[test]
+fn bare_command()
+{
+	use super::{PosixParser, BareCommand, ParseError};
+	let literal = PosixParser::new().bare_command(BareCommand::Literal);
+	let mut buf = String::from("\"cmd\" arg");
+	assert_eq!(literal.parse(&mut buf).collect::<Vec<_>>(), ["\"cmd\"", "arg"]);
+	// Quotes in the command don't join words
+	let mut buf = String::from("a'b\\ \"c d\" e\\ f");
+	assert_eq!(literal.parse(&mut buf).collect::<Vec<_>>(), ["a'b\\", "c d", "e f"]);
+	let mut buf = String::from("x\\ y z");
+	let mut words = literal.parse(&mut buf);
+	assert!(words.skip_word());
+	assert_eq!(words.collect::<Vec<_>>(), ["y", "z"]);
+
+	let error = PosixParser::new().bare_command(BareCommand::Error);
+	let mut buf = String::from("\"cmd\" arg");
+	let mut words = error.parse(&mut buf);
+	assert_eq!(words.next_result(), Some(Err(ParseError::QuotedCommand)));
+	assert_eq!(words.next_result(), None);
+	let mut buf = String::from("cmd 'arg 1' \"arg\\\\2\"");
+	assert_eq!(error.parse(&mut buf).collect_result().unwrap(), ["cmd", "arg 1", "arg\\2"]);
+}
+
+#[test]
+#[cfg(feature="std")]
+fn owned_word()
+{
+	use super::OwnedWord;
+	let mut buf = String::from(r#"a 'a' "a" a\' a 'a'"#);
+	let mut words = parse_posix(&mut buf);
+	let mut set = std::collections::HashSet::new();
+	while let Some(w) = words.next_word() {
+		set.insert(OwnedWord::from(w));
+	}
+	let w = |value: &str, quoted, escaped| OwnedWord { value: value.to_owned(), quoted, escaped, is_operator: false };
+	assert_eq!(set.len(), 3);
+	assert!(set.contains(&w("a", false, false)));
+	assert!(set.contains(&w("a", true, false)));
+	assert!(set.contains(&w("a'", false, true)));
+	// Operators are distinct from words with the same text
+	assert!(set.insert(OwnedWord { is_operator: true, ..w("a", false, false) }));
+}
+
+#[test]
+fn trailing_comment()
+{
+	let parser = super::PosixParser::new().comments(true);
+	let mut buf = String::from("run foo # do the thing");
+	let mut words = parser.parse(&mut buf);
+	assert_eq!(words.trailing_comment(), None);
+	assert_eq!(words.by_ref().collect::<Vec<_>>(), ["run", "foo"]);
+	assert_eq!(words.trailing_comment(), Some(" do the thing"));
+	assert_eq!(words.remainder(), "");
+	// Only at the start of an unquoted word
+	let mut buf = String::from("a#b '#c' \"#\"d #");
+	let mut words = parser.parse(&mut buf);
+	assert_eq!(words.by_ref().collect::<Vec<_>>(), ["a#b", "#c", "#d"]);
+	assert_eq!(words.trailing_comment(), Some(""));
+	// Skipping and popping also stop at the comment
+	let mut buf = String::from("a #b c");
+	let mut words = parser.parse(&mut buf);
+	assert!(words.skip_word());
+	assert!(!words.skip_word());
+	assert_eq!(words.trailing_comment(), Some("b c"));
+	let mut buf = String::from("a b #c");
+	assert_eq!(parser.parse(&mut buf).pop_word(), Some("b"));
+	// Disabled by default
+	let mut buf = String::from("a #b");
+	assert_eq!(parse_posix(&mut buf).collect::<Vec<_>>(), ["a", "#b"]);
+}
+
+#[test]
+fn multibyte_adjacent_to_escapes()
+{
+	let mut buf = String::from("caf\u{e9}\\ bar '\u{e9}'\\\u{e9}\"\u{1F600}\"\u{e9}\\\\ \\\u{2003}x");
+	assert_eq!(parse_posix(&mut buf).collect::<Vec<_>>(), ["caf\u{e9} bar", "\u{e9}\u{e9}\u{1F600}\u{e9}\\", "\u{2003}x"]);
+	// Including with the options that replace bytes
+	let parser = super::PosixParser::new().map_smart_quotes(true).unicode_whitespace(true).bash_double_quotes(true);
+	let mut buf = String::from("\u{201C}\u{e9} \u{e9}\u{201D}\u{3000}\"\\\u{e9}\"");
+	assert_eq!(parser.parse(&mut buf).collect::<Vec<_>>(), ["\u{e9} \u{e9}", "\\\u{e9}"]);
+	let mut buf = String::from("\u{e9}\\ \u{e9}");
+	assert_eq!(super::unescape_all(&mut buf[..]), "\u{e9} \u{e9}");
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn eof_is_final()
+{
+	let parser = super::PosixParser::new().eof_is_final(false);
+	let mut buf = String::from("foo ba");
+	let mut words = parser.parse(&mut buf);
+	assert_eq!(words.next(), Some("foo"));
+	assert_eq!(words.next(), None);
+	assert!(!words.skip_word());
+	let mut next = String::from(words.remainder());
+	next.push('r');
+	// Still not complete, until a separator is seen
+	assert_eq!(parser.parse(&mut next.clone()).count(), 0);
+	next.push(' ');
+	assert_eq!(parser.parse(&mut next).collect::<Vec<_>>(), ["bar"]);
+	// An unterminated quote is also partial, even with a separator at the end
+	let mut buf = String::from("a 'b ");
+	let mut words = parser.parse(&mut buf);
+	assert_eq!(words.by_ref().collect::<Vec<_>>(), ["a"]);
+	assert_eq!(words.remainder(), "'b ");
+	let mut buf = String::from("foo ba");
+	assert_eq!(parse_posix(&mut buf).collect::<Vec<_>>(), ["foo", "ba"]);
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn replace_word()
+{
+	let mut s = String::from("run old.txt -v");
+	assert!(super::replace_word(&mut s, 1, "new.txt"));
+	assert_eq!(s, "run new.txt -v");
+	// Other words (and the separators) keep their original form
+	let mut s = String::from("  a\\ b\t\"c d\"  e ");
+	assert!(super::replace_word(&mut s, 2, "it's"));
+	assert_eq!(s, "  a\\ b\t\"c d\"  'it'\\''s' ");
+	assert_eq!(parse_posix(&mut s).to_vec(), ["a b", "c d", "it's"]);
+	// Out of range
+	let mut s = String::from("a b");
+	assert!(!super::replace_word(&mut s, 2, "c"));
+	assert_eq!(s, "a b");
+}
+
+#[test]
+fn terminator()
+{
+	let terminators = |parser: super::PosixParser, s: &str| -> Vec<Option<u8>> {
+		let mut buf = String::from(s);
+		let mut words = parser.parse(&mut buf);
+		let mut rv = Vec::new();
+		while let Some(w) = words.next_word() {
+			rv.push(w.terminator());
+		}
+		rv
+	};
+	let p = super::PosixParser::new();
+	assert_eq!(terminators(p, "a b"), [Some(b' '), None]);
+	assert_eq!(terminators(p, "a\tb"), [Some(b'\t'), None]);
+	// The first byte of a run of separators, and EOF after trailing separators or a closing quote
+	assert_eq!(terminators(p, "a\r\n 'b'"), [Some(b'\r'), None]);
+	assert_eq!(terminators(p, "a\\ b\n  "), [Some(b'\n')]);
+	assert_eq!(terminators(p, "'a b"), [None]);
+	assert_eq!(terminators(p.soft_separator(b','), "a,b c"), [Some(b','), Some(b' '), None]);
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn single_quote_verbatim()
+{
+	let parser = super::PosixParser::new().single_quote_verbatim(true);
+	let mut buf = String::from(r"'a\b' '\\' '\n\t' 'x'\''y' '$HOME'");
+	assert_eq!(parser.parse(&mut buf).to_vec(), [r"a\b", r"\\", r"\n\t", "x'y", "$HOME"]);
+	// Overrides `single_quote_escapes`
+	let mut buf = String::from(r"'a\nb'");
+	assert_eq!(parser.single_quote_escapes(true).parse(&mut buf).to_vec(), [r"a\nb"]);
+	// Quoted output still parses back
+	let mut quoted = super::join(&[r"a\b", "it's"]);
+	assert_eq!(parser.parse(&mut quoted).to_vec(), [r"a\b", "it's"]);
+	// Skipping finds the same end
+	let mut buf = String::from(r"'a\' b");
+	let mut words = parser.parse(&mut buf);
+	assert!(words.skip_word());
+	assert_eq!(words.to_vec(), ["b"]);
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn quote_minimal()
+{
+	let cases: &[(&str, &str)] = &[
+		("abc", "abc"),
+		("caf\u{e9}-1.0", "caf\u{e9}-1.0"),
+		("", "''"),
+		("a b", "'a b'"),
+		("it's", "\"it's\""),
+		("a\\b", "\"a\\\\b\""),
+		("don't \"quote\"", "\"don't \\\"quote\\\"\""),
+		("\"quote\"", "'\"quote\"'"),
+		("it's $HOME", "'it'\\''s $HOME'"),
+		("*", "'*'"),
+		("a\nb", "'a\nb'"),
+		];
+	for &(word, expected) in cases {
+		let mut quoted = super::quote_minimal(word);
+		assert_eq!(quoted, expected);
+		assert!(quoted.len() <= super::quote(word).len());
+		assert_eq!(parse_posix(&mut quoted).collect::<Vec<_>>(), [word], "{:?}", word);
+	}
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn separator_classifier()
+{
+	let mut buf = String::from("ab1cd23 ef'4'g\"5\"");
+	assert_eq!(crate::parse_posix_with_classifier(&mut buf, |b| b.is_ascii_digit()).to_vec(), ["ab", "cd", " ef4g5"]);
+	// Works with the other word-boundary methods
+	let parser = super::PosixParser::new().separator_classifier(|b| b == b'/');
+	let mut buf = String::from("//usr/'local bin'//x");
+	let mut words = parser.parse(&mut buf);
+	assert!(words.skip_word());
+	assert_eq!(words.pop_word(), Some("x"));
+	assert_eq!(words.to_vec(), ["local bin"]);
+	// Capturing closures are accepted
+	let seps = [b';', b'|'];
+	let mut buf = String::from("a;b|'c;d' e");
+	assert_eq!(super::PosixParser::new().separator_classifier(|b| seps.contains(&b)).parse(&mut buf).to_vec(), ["a", "b", "c;d e"]);
+}
+
+#[test]
+fn word_as_bytes()
+{
+	let mut buf = *b"plain 'qu\xC3\xA9ted' \xC3 bin\\\xFF";
+	let mut words = parse_posix(&mut buf[..]);
+	let mut views = Vec::new();
+	while let Some(w) = words.next_word() {
+		assert_eq!(w.as_bytes(), w.value());
+		views.push((w.as_bytes(), w.as_str()));
+	}
+	assert_eq!(views, [
+		(&b"plain"[..], Some("plain")),
+		(&b"qu\xC3\xA9ted"[..], Some("qu\u{e9}ted")),
+		(&b"\xC3"[..], None),
+		(&b"bin\xFF"[..], None),
+		]);
+	// Also available for `str` input
+	let mut buf = String::from("a\\ b");
+	assert_eq!(parse_posix(&mut buf).next_word().unwrap().as_bytes(), b"a b");
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn segments()
+{
+	use super::{segments, Segment};
+	let u = |s: &str| Segment::Unquoted(s.to_owned());
+	let s = |s: &str| Segment::Single(s.to_owned());
+	let d = |s: &str| Segment::Double(s.to_owned());
+	assert_eq!(segments("a'b'c"), [(0, u("a")), (0, s("b")), (0, u("c"))]);
+	assert_eq!(segments(r#"  x\ y'' "p q"\'end "unterminated"#), [
+		(0, u("x y")), (0, s("")),
+		(1, d("p q")), (1, u("'end")),
+		(2, d("unterminated")),
+		]);
+	assert_eq!(segments(" \t"), []);
+	// The parts join to make the same words as the parser
+	let line = r#"it's "a \"test\""'  'of\ segments"#;
+	let mut words: Vec<String> = Vec::new();
+	for (i, seg) in segments(line) {
+		if words.len() == i {
+			words.push(String::new());
+		}
+		match seg
+		{
+		Segment::Unquoted(v) | Segment::Single(v) | Segment::Double(v) => words[i].push_str(&v),
+		}
+	}
+	assert_eq!(words, parse_posix(&mut String::from(line)).to_vec());
+}
+
+#[test]
+#[cfg(feature="alloc")]
+fn on_invalid_escape()
+{
+	use super::{PosixParser, InvalidEscape, ParseError};
+	let input = r#"\a b\- "\q\"" '\z' ok\ \n"#;
+	let parse = |mode| {
+		let mut buf = String::from(input);
+		let mut words = PosixParser::new().on_invalid_escape(mode).parse(&mut buf);
+		let rv = words.by_ref().map(|w| w.to_owned()).collect::<Vec<_>>();
+		(rv, words.errors().to_vec())
+	};
+	assert_eq!(parse(InvalidEscape::Skip), (vec!["".to_owned(), "b".into(), "\"".into(), "".into(), "ok \n".into()], vec![]));
+	assert_eq!(parse(InvalidEscape::Keep).0, [r"\a", r"b\-", r#"\q""#, r"\z", "ok \n"]);
+	let (words, errors) = parse(InvalidEscape::Recover);
+	assert_eq!(words, parse(InvalidEscape::Skip).0);
+	assert_eq!(errors, [ParseError::InvalidEscape; 4]);
+	// No errors for escapes that other options accept
+	let mut buf = String::from(r"\a '\b'");
+	let mut words = PosixParser::new().on_invalid_escape(InvalidEscape::Recover).bash_compatible_escapes(true).parse(&mut buf);
+	assert_eq!(words.by_ref().count(), 2);
+	assert_eq!(words.errors(), [ParseError::InvalidEscape]);
+}
+
+#[test]
+fn semicolon()
+{
+	use super::{PosixParser, Semicolon};
+	let parser = PosixParser::new().semicolon(Semicolon::Marker);
+	let mut buf = String::from("echo a; echo b");
+	let mut words = parser.parse(&mut buf);
+	let mut statements = vec![vec![]];
+	while let Some(w) = words.next_word() {
+		if w.is_semicolon() {
+			statements.push(vec![]);
+		}
+		else {
+			statements.last_mut().unwrap().push(w.value());
+		}
+	}
+	assert_eq!(statements, [["echo", "a"], ["echo", "b"]]);
+	// Quoted and escaped semicolons are literal, and one doesn't need to be separated
+	let mut buf = String::from(r#"a;b ";" \; c;;"#);
+	let words = parser.parse(&mut buf).map(|w| w.to_owned()).collect::<Vec<_>>();
+	assert_eq!(words, ["a", ";", "b", ";", ";", "c", ";", ";"]);
+	let mut buf = String::from(r#"x ";" \;"#);
+	let mut words = parser.parse(&mut buf);
+	assert!(!words.next_word().unwrap().is_semicolon());
+	assert!(!words.next_word().unwrap().is_semicolon());
+	assert!(!words.next_word().unwrap().is_semicolon());
+	// Skipping a marker
+	let mut buf = String::from("a ; b");
+	let mut words = parser.parse(&mut buf);
+	assert!(words.skip_word());
+	assert!(words.skip_word());
+	assert_eq!(words.next(), Some("b"));
+
+	// Ending iteration, with the next statement available from the remainder
+	let parser = PosixParser::new().semicolon(Semicolon::End);
+	let mut buf = String::from("echo a; echo b");
+	let mut words = parser.parse(&mut buf);
+	assert_eq!(words.by_ref().collect::<Vec<_>>(), ["echo", "a"]);
+	let rest = words.into_remainder();
+	assert_eq!(rest, "echo b");
+	assert_eq!(parser.parse(rest).collect::<Vec<_>>(), ["echo", "b"]);
+	let mut buf = String::from("echo a;b c");
+	let mut words = parser.parse(&mut buf);
+	assert_eq!(words.pop_word(), Some("a"));
+	assert_eq!(words.next(), Some("echo"));
+	assert_eq!(words.next(), None);
+	// The default has no special handling
+	let mut buf = String::from("echo a; b");
+	assert_eq!(PosixParser::new().parse(&mut buf).collect::<Vec<_>>(), ["echo", "a;", "b"]);
+}
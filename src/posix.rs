@@ -1,153 +1,2538 @@
 use crate::split_off_front_inplace_mut;
+use crate::ByteString;
 use crate::ByteStringSlice;
 
 #[cfg(test)]
 #[path="posix-test.rs"]
 mod test;
 
+#[path="posix-quote.rs"]
+mod quote;
+pub use self::quote::*;
+
+#[path="posix-scan.rs"]
+mod scan;
+pub use self::scan::*;
+
+#[path="posix-lexer.rs"]
+mod lexer;
+pub use self::lexer::*;
+
+#[cfg(feature="args")]
+#[path="posix-args.rs"]
+mod args;
+#[cfg(feature="args")]
+pub use self::args::*;
+
+#[path="posix-fixed.rs"]
+mod fixed;
+pub use self::fixed::*;
+
+#[cfg(feature="alloc")]
+#[path="posix-stream.rs"]
+mod stream;
+#[cfg(feature="alloc")]
+pub use self::stream::*;
+
+#[derive(Copy,Clone,PartialEq,Debug)]
 enum PosixEscapeMode
 {
-	Outer,
-	OuterSlash,
-	SingleQuote,
-	SingleQuoteSlash,
-	DoubleQuote,
-	DoubleQuoteSlash,
+	Outer,
+	OuterSlash,
+	SingleQuote,
+	SingleQuoteSlash,
+	DoubleQuote,
+	DoubleQuoteSlash,
+}
+
+/// Single-byte state transition for POSIX escaping
+///
+/// Returns the new mode, and the byte to emit (if any). Separators are handled by the caller, as they end the word
+/// instead of producing output.
+#[inline]
+fn step(mode: PosixEscapeMode, byte: u8) -> (PosixEscapeMode, Option<u8>)
+{
+	match mode
+	{
+	PosixEscapeMode::Outer => match byte
+		{
+		b'\\' => (PosixEscapeMode::OuterSlash, None),
+		b'\'' => (PosixEscapeMode::SingleQuote, None),
+		b'"' => (PosixEscapeMode::DoubleQuote, None),
+		v => (mode, Some(v)),
+		},
+	PosixEscapeMode::OuterSlash => (PosixEscapeMode::Outer, match byte
+		{
+		v @ b' ' => Some(v),
+		v @ b'\t' => Some(v),
+		v @ b'\n' => Some(v),
+		v @ b'\r' => Some(v),
+		v @ b'\'' => Some(v),
+		v @ b'\"' => Some(v),
+		v @ b'\\' => Some(v),
+		b'n' => Some(b'\n'),
+		b'r' => Some(b'\r'),
+		b't' => Some(b'\t'),
+		// Non-ASCII is passed through, as dropping part of a multi-byte sequence would break UTF-8
+		v if v >= 0x80 => Some(v),
+		_ => None,	// TODO: What to to on an invalid escape?
+		}),
+	PosixEscapeMode::SingleQuote => match byte
+		{
+		b'\\' => (PosixEscapeMode::SingleQuoteSlash, None),
+		b'\'' => (PosixEscapeMode::Outer, None),
+		v => (mode, Some(v)),
+		},
+	PosixEscapeMode::SingleQuoteSlash => (PosixEscapeMode::SingleQuote, match byte
+		{
+		v @ b'\'' => Some(v),
+		v @ b'\\' => Some(v),
+		// Non-ASCII is passed through, as dropping part of a multi-byte sequence would break UTF-8
+		v if v >= 0x80 => Some(v),
+		_ => None,	// TODO: What to to on an invalid escape?
+		}),
+	PosixEscapeMode::DoubleQuote => match byte
+		{
+		b'\\' => (PosixEscapeMode::DoubleQuoteSlash, None),
+		b'"' => (PosixEscapeMode::Outer, None),
+		v => (mode, Some(v)),
+		},
+	PosixEscapeMode::DoubleQuoteSlash => (PosixEscapeMode::DoubleQuote, match byte
+		{
+		v @ b'\'' => Some(v),
+		v @ b'\"' => Some(v),
+		v @ b'\\' => Some(v),
+		b'n' => Some(b'\n'),
+		b'r' => Some(b'\r'),
+		b't' => Some(b'\t'),
+		// Non-ASCII is passed through, as dropping part of a multi-byte sequence would break UTF-8
+		v if v >= 0x80 => Some(v),
+		_ => None,	// TODO: What to to on an invalid escape?
+		}),
+	}
+}
+
+/// Bytes emitted by [PosixParser::step] (up to two)
+#[derive(Copy,Clone,PartialEq,Debug)]
+struct Emit([u8; 2], usize);
+impl Emit
+{
+	const NONE: Emit = Emit([0; 2], 0);
+	fn one(v: u8) -> Emit {
+		Emit([v, 0], 1)
+	}
+	/// NOTE: Only valid after an escape character has been consumed (so there's space for both bytes)
+	fn two(a: u8, b: u8) -> Emit {
+		Emit([a, b], 2)
+	}
+	fn as_slice(&self) -> &[u8] {
+		&self.0[..self.1]
+	}
+}
+
+/// The default POSIX escaping rules as a [crate::dialect::Dialect]
+///
+/// Gives the same words as [crate::parse_posix], but without any of the [PosixParser] options or [Word] metadata.
+/// [PosixShellWords] doesn't use this, so that it can support those options (and copy runs of plain bytes at once).
+#[derive(Copy,Clone,Debug,Default)]
+pub struct PosixDialect;
+
+/// Opaque state for [PosixDialect]
+#[derive(Copy,Clone,Debug)]
+pub struct PosixState(PosixEscapeMode);
+impl Default for PosixState {
+	fn default() -> Self {
+		PosixState(PosixEscapeMode::Outer)
+	}
+}
+
+impl crate::dialect::Dialect for PosixDialect
+{
+	type State = PosixState;
+
+	fn step(&self, state: PosixState, byte: u8) -> (PosixState, crate::dialect::Action) {
+		use crate::dialect::Action;
+		if state.0 == PosixEscapeMode::Outer && is_separator(byte) {
+			return (state, Action::Break);
+		}
+		let (mode, out) = step(state.0, byte);
+		(PosixState(mode), match out
+			{
+			Some(b) if b == byte => Action::Continue,
+			Some(b) => Action::Emit(b),
+			None => Action::Skip,
+			})
+	}
+
+	fn is_separator(&self, byte: u8) -> bool {
+		is_separator(byte)
+	}
+}
+
+/// Returns true if the byte ends a word (when not quoted or escaped)
+#[inline]
+pub(crate) const fn is_separator(byte: u8) -> bool
+{
+	matches!(byte, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+/// Returns true if the byte has a special meaning outside of quotes (whitespace, backslash, and quotes)
+///
+/// All other bytes are copied unchanged when not quoted or escaped.
+///
+/// ```
+/// use cmdline_words_parser::posix::is_posix_special;
+/// const TABLE: [bool; 256] = {
+///     let mut t = [false; 256];
+///     let mut i = 0;
+///     while i < 256 { t[i] = is_posix_special(i as u8); i += 1; }
+///     t
+/// };
+/// assert!( TABLE[b'"' as usize] );
+/// assert!( !TABLE[b'a' as usize] );
+/// ```
+#[inline]
+pub const fn is_posix_special(byte: u8) -> bool
+{
+	is_separator(byte) || matches!(byte, b'\\' | b'\'' | b'"')
+}
+
+/// Returns true for the glob metacharacters (`*`, `?`, and `[`)
+fn is_glob(byte: u8) -> bool
+{
+	matches!(byte, b'*' | b'?' | b'[')
+}
+
+/// Strip leading and trailing ASCII whitespace (`<[u8]>::trim_ascii` requires Rust 1.80)
+fn trim_ascii(bytes: &[u8]) -> &[u8]
+{
+	let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+	let end = bytes.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(start, |i| i + 1);
+	&bytes[start .. end]
+}
+
+/// Decode the UTF-8 code point at the start of `bytes`, returning it and its encoded length
+fn decode_char(bytes: &[u8]) -> Option<(char, usize)>
+{
+	let len = match bytes.first()
+		{
+		Some(&b) if b < 0x80 => 1,
+		Some(&b) if b & 0xE0 == 0xC0 => 2,
+		Some(&b) if b & 0xF0 == 0xE0 => 3,
+		Some(&b) if b & 0xF8 == 0xF0 => 4,
+		_ => return None,
+		};
+	let s = ::std::str::from_utf8(bytes.get(..len)?).ok()?;
+	s.chars().next().map(|c| (c, len))
+}
+
+/// Get the ASCII equivalent of a unicode smart quote at the start of `bytes`
+fn smart_quote(bytes: &[u8]) -> Option<u8>
+{
+	match bytes
+	{
+	[0xE2, 0x80, 0x98, ..] | [0xE2, 0x80, 0x99, ..] => Some(b'\''),
+	[0xE2, 0x80, 0x9C, ..] | [0xE2, 0x80, 0x9D, ..] => Some(b'"'),
+	_ => None,
+	}
+}
+
+/// Builder for a configured POSIX parser
+///
+/// Holds the parsing options, and can be used to parse any number of strings.
+///
+/// ```
+/// let mut cmdline = String::from("Hello\u{A0}World");
+/// let mut parse = ::cmdline_words_parser::PosixParser::new().unicode_whitespace(true).parse(&mut cmdline);
+/// assert_eq!( parse.next(), Some("Hello") );
+/// assert_eq!( parse.next(), Some("World") );
+/// assert_eq!( parse.next(), None );
+/// ```
+#[derive(Copy,Clone,Debug)]
+pub struct PosixParser<C = fn(u8) -> bool>
+{
+	options: Options,
+	classifier: Option<C>,
+}
+
+/// Everything in a [PosixParser] apart from the classifier (so can be moved between classifier types)
+#[derive(Copy,Clone,Debug)]
+struct Options
+{
+	unicode_whitespace: bool,
+	cr_mode: CrMode,
+	map_smart_quotes: bool,
+	on_unterminated: Unterminated,
+	max_token_len: usize,
+	on_too_long: TooLong,
+	bash_escapes: bool,
+	bash_double_quotes: bool,
+	keep_enclosing_quotes: bool,
+	soft_separator: Option<u8>,
+	ascii_lowercase: bool,
+	double_quote_as_literal: bool,
+	stop_token: Option<StopToken>,
+	single_quote_escapes: bool,
+	raw: bool,
+	substitutions: bool,
+	command_separator: Option<u8>,
+	backslash_newline_separates: bool,
+	trim_tokens: bool,
+	bare_command: BareCommand,
+	comments: bool,
+	eof_is_final: bool,
+	single_quote_verbatim: bool,
+	on_invalid_escape: InvalidEscape,
+	semicolon: Semicolon,
+}
+
+/// Longest token accepted by [PosixParser::stop_token]
+pub const MAX_STOP_TOKEN_LEN: usize = 32;
+
+/// A copy of a [PosixParser::stop_token], so the parser doesn't need to borrow it
+#[derive(Copy,Clone,Debug)]
+struct StopToken
+{
+	bytes: [u8; MAX_STOP_TOKEN_LEN],
+	len: u8,
+}
+
+impl StopToken
+{
+	fn as_slice(&self) -> &[u8] {
+		&self.bytes[..self.len as usize]
+	}
+}
+
+impl Default for PosixParser
+{
+	fn default() -> Self {
+		PosixParser::new()
+	}
+}
+
+/// Handling of words longer than [PosixParser::max_token_len]
+#[derive(Copy,Clone,PartialEq,Eq,Debug,Default)]
+pub enum TooLong
+{
+	/// Return [ParseError::TokenTooLong] from [PosixShellWords::next_result] (the default)
+	#[default]
+	Error,
+	/// Truncate the word to the limit (on a UTF-8 character boundary), and continue
+	Truncate,
+}
+
+/// Handling of a backslash followed by a character that can't be escaped (e.g. `\x`), see
+/// [PosixParser::on_invalid_escape]
+#[derive(Copy,Clone,PartialEq,Eq,Debug,Default)]
+pub enum InvalidEscape
+{
+	/// Drop the character (the default)
+	#[default]
+	Skip,
+	/// Keep the backslash and the character
+	Keep,
+	/// Drop the character, and record [ParseError::InvalidEscape] in [PosixShellWords::errors]
+	#[cfg(feature="alloc")]
+	Recover,
+}
+
+/// Handling of an unquoted (and unescaped) `;`, see [PosixParser::semicolon]
+#[derive(Copy,Clone,PartialEq,Eq,Debug,Default)]
+pub enum Semicolon
+{
+	/// Part of the word, like any other character (the default)
+	#[default]
+	Literal,
+	/// Ends the word, and is returned as a `";"` word (with [Word::is_semicolon] set)
+	Marker,
+	/// Ends the word and iteration, leaving the following statements in [PosixShellWords::remainder]
+	End,
+}
+
+/// Handling of the first word (the command name), see [PosixParser::bare_command]
+#[derive(Copy,Clone,PartialEq,Eq,Debug,Default)]
+pub enum BareCommand
+{
+	/// Parsed the same as the other words (the default)
+	#[default]
+	Off,
+	/// Quotes and backslashes are kept as literal characters
+	Literal,
+	/// Quotes and backslashes are an error ([ParseError::QuotedCommand] from [PosixShellWords::next_result])
+	Error,
+}
+
+/// Handling of a quote or escape that isn't closed before the end of the input, see [PosixParser::on_unterminated]
+#[derive(Copy,Clone,PartialEq,Eq,Debug,Default)]
+pub enum Unterminated
+{
+	/// Return the word as-is, with any trailing backslash removed (the default)
+	#[default]
+	Accept,
+	/// Return [ParseError::UnterminatedEscape] from [PosixShellWords::next_result] if the input ends with a
+	/// backslash (quoted or not), otherwise [ParseError::UnterminatedQuote]
+	Error,
+	/// Silently discard the final word
+	Drop,
+}
+
+/// Error from the fallible parsing methods (e.g. [PosixShellWords::next_result])
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
+pub enum ParseError
+{
+	/// The input ended within a quoted region
+	UnterminatedQuote,
+	/// The input ended with a backslash (i.e. an escape with nothing to escape)
+	UnterminatedEscape,
+	/// A word was longer than the configured limit
+	TokenTooLong,
+	/// A backslash was followed by a character that can't be escaped (recorded with [InvalidEscape::Recover])
+	InvalidEscape,
+	/// The first word contained a quote or backslash, with [BareCommand::Error]
+	QuotedCommand,
+	/// The input had no words (see [crate::try_parse_posix_nonempty])
+	EmptyInput,
+}
+impl ::std::fmt::Display for ParseError
+{
+	fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+		match self
+		{
+		ParseError::UnterminatedQuote => f.write_str("unterminated quote"),
+		ParseError::UnterminatedEscape => f.write_str("trailing backslash"),
+		ParseError::TokenTooLong => f.write_str("word too long"),
+		ParseError::InvalidEscape => f.write_str("invalid escape"),
+		ParseError::QuotedCommand => f.write_str("quoted command name"),
+		ParseError::EmptyInput => f.write_str("empty input"),
+		}
+	}
+}
+#[cfg(feature="std")]
+impl ::std::error::Error for ParseError
+{
+}
+
+/// Error from [parse_fixed], when the input had the wrong number of words
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
+pub struct ArityError
+{
+	/// Number of words wanted
+	pub expected: usize,
+	/// Number of words in the input
+	pub actual: usize,
+}
+impl ::std::fmt::Display for ArityError
+{
+	fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+		write!(f, "expected {} words, got {}", self.expected, self.actual)
+	}
+}
+#[cfg(feature="std")]
+impl ::std::error::Error for ArityError
+{
+}
+
+/// Get the error for input ending in `mode`
+fn unterminated_error(mode: PosixEscapeMode) -> ParseError
+{
+	match mode
+	{
+	// A trailing backslash is reported even if it's also within an unterminated quote
+	PosixEscapeMode::OuterSlash | PosixEscapeMode::SingleQuoteSlash | PosixEscapeMode::DoubleQuoteSlash => ParseError::UnterminatedEscape,
+	_ => ParseError::UnterminatedQuote,
+	}
+}
+
+/// Handling of carriage return (`\r`) bytes, see [PosixParser::cr_mode]
+#[derive(Copy,Clone,PartialEq,Eq,Debug,Default)]
+pub enum CrMode
+{
+	/// Treated as whitespace, splitting words (the default)
+	#[default]
+	Separator,
+	/// Removed from the output wherever it appears
+	Ignore,
+	/// Kept in the word as a normal character
+	Literal,
+}
+
+impl PosixParser
+{
+	/// Create a parser with the default options (matching [crate::parse_posix])
+	///
+	/// This is a `const fn`, so a configured parser can be stored in a `const` or `static`.
+	pub const fn new() -> PosixParser {
+		PosixParser {
+			options: Options {
+				unicode_whitespace: false,
+				cr_mode: CrMode::Separator,
+				map_smart_quotes: false,
+				on_unterminated: Unterminated::Accept,
+				max_token_len: usize::MAX,
+				on_too_long: TooLong::Error,
+				bash_escapes: false,
+				bash_double_quotes: false,
+				keep_enclosing_quotes: false,
+				soft_separator: None,
+				ascii_lowercase: false,
+				double_quote_as_literal: false,
+				stop_token: None,
+				single_quote_escapes: false,
+				raw: false,
+				substitutions: false,
+				command_separator: None,
+				backslash_newline_separates: false,
+				trim_tokens: false,
+				bare_command: BareCommand::Off,
+				comments: false,
+				eof_is_final: true,
+				single_quote_verbatim: false,
+				on_invalid_escape: InvalidEscape::Skip,
+				semicolon: Semicolon::Literal,
+				},
+			classifier: None,
+			}
+	}
+}
+impl<C: Fn(u8) -> bool + Copy> PosixParser<C>
+{
+	/// Also split on unicode `White_Space` code points (e.g. U+00A0 NO-BREAK SPACE, U+3000 IDEOGRAPHIC SPACE)
+	///
+	/// Only meaningful for UTF-8 input (`str`/`String`), byte input that isn't UTF-8 is never split by this.
+	pub fn unicode_whitespace(mut self, enable: bool) -> Self {
+		self.options.unicode_whitespace = enable;
+		self
+	}
+
+	/// Set how carriage returns are handled (default [CrMode::Separator])
+	pub fn cr_mode(mut self, mode: CrMode) -> Self {
+		self.options.cr_mode = mode;
+		self
+	}
+
+	/// Treat unicode "smart quotes" as their ASCII equivalents
+	///
+	/// U+2018 and U+2019 (`‘’`) act as `'`, and U+201C and U+201D (`“”`) act as `"`, including when escaped or
+	/// within the other kind of quote (where the ASCII version is emitted). Only meaningful for UTF-8 input.
+	pub fn map_smart_quotes(mut self, enable: bool) -> Self {
+		self.options.map_smart_quotes = enable;
+		self
+	}
+
+	/// Set the handling of an unterminated quote or escape at the end of the input (default [Unterminated::Accept])
+	pub fn on_unterminated(mut self, mode: Unterminated) -> Self {
+		self.options.on_unterminated = mode;
+		self
+	}
+
+	/// Limit the length (in bytes, after unescaping) of each word (default unlimited)
+	///
+	/// Longer words are handled according to [PosixParser::on_token_too_long]
+	pub fn max_token_len(mut self, len: usize) -> Self {
+		self.options.max_token_len = len;
+		self
+	}
+	/// Set the handling of words longer than [PosixParser::max_token_len] (default [TooLong::Error])
+	pub fn on_token_too_long(mut self, mode: TooLong) -> Self {
+		self.options.on_too_long = mode;
+		self
+	}
+
+	/// Use bash's rules for backslash escapes outside of quotes
+	///
+	/// A backslash preserves the literal value of the following character, whatever it is (so `\q` is `q`, `\$` is
+	/// `$`, and `\n` is `n` instead of a newline). A backslash followed by a newline is a line continuation, and is
+	/// removed entirely.
+	pub fn bash_compatible_escapes(mut self, enable: bool) -> Self {
+		self.options.bash_escapes = enable;
+		self
+	}
+
+	/// Use bash's rules for backslash escapes within double quotes
+	///
+	/// Only `$`, `` ` ``, `"`, `\`, and newline can be escaped (a backslash-newline is removed entirely), before
+	/// anything else the backslash is kept (so `"\n"` is a backslash followed by `n`).
+	pub fn bash_double_quotes(mut self, enable: bool) -> Self {
+		self.options.bash_double_quotes = enable;
+		self
+	}
+
+	/// Use the escaping behaviour of real shells (bash), instead of this crate's C-like escapes
+	///
+	/// Enables both [PosixParser::bash_compatible_escapes] and [PosixParser::bash_double_quotes], so escapes such as
+	/// `\n` are never converted to control characters.
+	pub fn shell_accurate_escapes(mut self, enable: bool) -> Self {
+		self.options.bash_escapes = enable;
+		self.options.bash_double_quotes = enable;
+		self
+	}
+
+	/// Keep the quotes around words that are entirely within one pair of quotes (e.g. `"a b"` is returned as
+	/// `"a b"`, but `a"b"` is returned as `ab`)
+	///
+	/// Escapes within the quotes are still processed, so this is only reversible if the word contains no escaped
+	/// quotes (`"a\"b"` becomes `"a"b"`).
+	pub fn keep_enclosing_quotes(mut self, enable: bool) -> Self {
+		self.options.keep_enclosing_quotes = enable;
+		self
+	}
+
+	/// Also split words on an (unquoted, unescaped) `sep`, which is reported by [Word::trailing_separator]
+	///
+	/// Unlike whitespace, repeated soft separators are not merged (so `a::b` yields `a`, an empty word, then `b`),
+	/// which matches how `PATH`-style lists are interpreted. A trailing soft separator does not produce a final
+	/// empty word. As with non-whitespace characters in a shell's `IFS`, whitespace around a soft separator is part of
+	/// the same delimiter (so `a : b` is the same as `a:b`).
+	///
+	/// ```
+	/// let mut path = String::from("/usr/bin:/bin");
+	/// let mut words = ::cmdline_words_parser::PosixParser::new().soft_separator(b':').parse(&mut path);
+	/// let w = words.next_word().unwrap();
+	/// assert_eq!( (w.value(), w.trailing_separator()), ("/usr/bin", Some(b':')) );
+	/// let w = words.next_word().unwrap();
+	/// assert_eq!( (w.value(), w.trailing_separator()), ("/bin", None) );
+	/// ```
+	pub fn soft_separator(mut self, sep: u8) -> Self {
+		self.options.soft_separator = Some(sep);
+		self
+	}
+
+	/// Convert ASCII letters in the output to lowercase (e.g. for case-insensitive command matching)
+	///
+	/// Only ASCII is changed (so the length, and UTF-8 validity, are unaffected), other characters are kept as-is.
+	pub fn ascii_lowercase(mut self, enable: bool) -> Self {
+		self.options.ascii_lowercase = enable;
+		self
+	}
+
+	/// Within double quotes, treat `""` as a literal `"` (as in CSV), instead of closing and re-opening the quotes
+	///
+	/// ```
+	/// let mut s = String::from(r#""a""b" """"#);
+	/// let mut words = ::cmdline_words_parser::PosixParser::new().double_quote_as_literal(true).parse(&mut s);
+	/// assert_eq!( words.next(), Some(r#"a"b"#) );
+	/// assert_eq!( words.next(), Some(r#"""#) );
+	/// ```
+	pub fn double_quote_as_literal(mut self, enable: bool) -> Self {
+		self.options.double_quote_as_literal = enable;
+		self
+	}
+
+	/// Process the full set of escapes (e.g. `\n`) within single quotes, the same as within double quotes
+	///
+	/// This is non-standard (POSIX shells treat everything within single quotes literally), but is used by some
+	/// configuration formats. By default only `\'` and `\\` are escapes within single quotes.
+	///
+	/// ```
+	/// let mut s = String::from(r"'a\nb'");
+	/// let mut words = ::cmdline_words_parser::PosixParser::new().single_quote_escapes(true).parse(&mut s);
+	/// assert_eq!( words.next(), Some("a\nb") );
+	/// ```
+	pub fn single_quote_escapes(mut self, enable: bool) -> Self {
+		self.options.single_quote_escapes = enable;
+		self
+	}
+
+	/// Treat everything within single quotes literally (including backslashes), as POSIX shells do
+	///
+	/// By default `\'` and `\\` are escapes within single quotes. This takes precedence over
+	/// [PosixParser::single_quote_escapes].
+	///
+	/// ```
+	/// let mut s = String::from(r"'a\b' 'c\'d");
+	/// let mut words = ::cmdline_words_parser::PosixParser::new().single_quote_verbatim(true).parse(&mut s);
+	/// assert_eq!( words.next(), Some(r"a\b") );
+	/// assert_eq!( words.next(), Some(r"c\d") );
+	/// ```
+	pub fn single_quote_verbatim(mut self, enable: bool) -> Self {
+		self.options.single_quote_verbatim = enable;
+		self
+	}
+
+	/// Don't process quotes or escapes, words are only split on whitespace (and soft separators)
+	///
+	/// Mostly useful with [PosixShellWords::next_with_opts], for words following a flag like `--raw`.
+	pub fn raw(mut self, enable: bool) -> Self {
+		self.options.raw = enable;
+		self
+	}
+
+	/// Pass command substitutions (`$(...)`) and arithmetic expansions (`$((...))`) through unchanged
+	///
+	/// The substitution is kept as part of the word (including the `$(` and `)`), with no splitting or unescaping
+	/// within it, so the caller can evaluate it. Recognised outside of quotes and within double quotes.
+	///
+	/// ```
+	/// let mut s = String::from("echo $(( 1 + 2 )) \"$(date +%Y)\"_log done");
+	/// let words: Vec<_> = ::cmdline_words_parser::PosixParser::new().substitutions(true).parse(&mut s).collect();
+	/// assert_eq!( words, ["echo", "$(( 1 + 2 ))", "$(date +%Y)_log", "done"] );
+	/// ```
+	pub fn substitutions(mut self, enable: bool) -> Self {
+		self.options.substitutions = enable;
+		self
+	}
+
+	/// Set an additional (unquoted) byte that ends the first word, e.g. `:` for `cmd:arg1 arg2`
+	///
+	/// Only applies to the first word (including when it's skipped), and acts like a [PosixParser::soft_separator]
+	/// for that word (replacing any configured soft separator).
+	///
+	/// ```
+	/// let mut cmdline = String::from("run:a:b c");
+	/// let words: Vec<_> = ::cmdline_words_parser::PosixParser::new().command_separator(b':').parse(&mut cmdline).collect();
+	/// assert_eq!( words, ["run", "a:b", "c"] );
+	/// ```
+	pub fn command_separator(mut self, sep: u8) -> Self {
+		self.options.command_separator = Some(sep);
+		self
+	}
+
+	/// Treat an unquoted backslash followed by a newline as a separator (instead of an escaped newline)
+	///
+	/// This takes precedence over the line continuation from [PosixParser::bash_compatible_escapes].
+	///
+	/// ```
+	/// let mut s = String::from("cmd \\\n  --flag\\\nfile");
+	/// let words: Vec<_> = ::cmdline_words_parser::PosixParser::new().backslash_newline_separates(true).parse(&mut s).collect();
+	/// assert_eq!( words, ["cmd", "--flag", "file"] );
+	/// ```
+	pub fn backslash_newline_separates(mut self, enable: bool) -> Self {
+		self.options.backslash_newline_separates = enable;
+		self
+	}
+
+	/// Trim ASCII whitespace from the start and end of each word (after unescaping)
+	///
+	/// Note that this also trims whitespace that was quoted or escaped.
+	///
+	/// ```
+	/// let mut s = String::from("key=' spaced value ' \\ x");
+	/// let words: Vec<_> = ::cmdline_words_parser::PosixParser::new().trim_tokens(true).parse(&mut s).collect();
+	/// assert_eq!( words, ["key= spaced value", "x"] );
+	/// ```
+	pub fn trim_tokens(mut self, enable: bool) -> Self {
+		self.options.trim_tokens = enable;
+		self
+	}
+
+	/// Require the first word (the command name) to be a bare word, with no quotes or escapes
+	///
+	/// The first word is only split on whitespace, and quotes/backslashes in it are either kept or an error.
+	///
+	/// ```
+	/// use cmdline_words_parser::{PosixParser, posix::BareCommand};
+	/// let mut s = String::from(r#""cmd" 'arg 1'"#);
+	/// let words: Vec<_> = PosixParser::new().bare_command(BareCommand::Literal).parse(&mut s).collect();
+	/// assert_eq!( words, [r#""cmd""#, "arg 1"] );
+	/// ```
+	pub fn bare_command(mut self, mode: BareCommand) -> Self {
+		self.options.bare_command = mode;
+		self
+	}
+
+	/// Treat an unquoted `#` at the start of a word as a comment, which ends iteration
+	///
+	/// The comment runs until the end of the line, and is available from [PosixShellWords::trailing_comment]. Any
+	/// following lines are left in [PosixShellWords::remainder].
+	pub fn comments(mut self, enable: bool) -> Self {
+		self.options.comments = enable;
+		self
+	}
+
+	/// Set whether the end of the input ends the final word (the default), or more input may follow
+	///
+	/// When disabled, a word that runs to the end of the input isn't returned (iteration ends before it), and is left
+	/// in [PosixShellWords::remainder] so it can be parsed again once more input is available. [WordStream] handles
+	/// this automatically, until [WordStream::finish] is called.
+	///
+	/// ```
+	/// let parser = ::cmdline_words_parser::PosixParser::new().eof_is_final(false);
+	/// let mut s = String::from("foo ba");
+	/// let mut words = parser.parse(&mut s);
+	/// assert_eq!( words.by_ref().collect::<Vec<_>>(), ["foo"] );
+	/// assert_eq!( words.remainder(), "ba" );
+	/// ```
+	pub fn eof_is_final(mut self, enable: bool) -> Self {
+		self.options.eof_is_final = enable;
+		self
+	}
+
+	/// Use `is_sep` to decide which (unquoted, unescaped) bytes split words, instead of ASCII whitespace
+	///
+	/// This is called for every byte outside of quotes, so is slower than the built-in separators. Quotes and escapes
+	/// are still processed as normal. `is_sep` can be any (`Copy`) closure, this changes the parser's type parameter.
+	/// See also [crate::parse_posix_with_classifier].
+	///
+	/// ```
+	/// let mut s = String::from("a,b 'c,d'");
+	/// let words: Vec<_> = ::cmdline_words_parser::PosixParser::new().separator_classifier(|b| b == b',').parse(&mut s).collect();
+	/// assert_eq!( words, ["a", "b c,d"] );
+	/// ```
+	pub fn separator_classifier<F: Fn(u8) -> bool + Copy>(self, is_sep: F) -> PosixParser<F> {
+		PosixParser {
+			options: self.options,
+			classifier: Some(is_sep),
+			}
+	}
+
+	/// Set the handling of a backslash followed by a character that can't be escaped (e.g. `\x`)
+	///
+	/// ```
+	/// use cmdline_words_parser::{PosixParser, posix::InvalidEscape};
+	/// let mut s = String::from(r"C:\x\'y");
+	/// let words: Vec<_> = PosixParser::new().on_invalid_escape(InvalidEscape::Keep).parse(&mut s).collect();
+	/// assert_eq!( words, [r"C:\x'y"] );
+	/// ```
+	pub fn on_invalid_escape(mut self, mode: InvalidEscape) -> Self {
+		self.options.on_invalid_escape = mode;
+		self
+	}
+
+	/// Set the handling of an unquoted `;` (the statement separator), which doesn't need to be surrounded by
+	/// whitespace
+	///
+	/// A quoted (`";"`) or escaped (`\;`) semicolon is always part of the word. With [Semicolon::Marker] the word
+	/// after a `;` is the command for [PosixParser::command_separator] and [PosixParser::bare_command].
+	///
+	/// ```
+	/// use cmdline_words_parser::{PosixParser, posix::Semicolon};
+	/// let mut s = String::from(r"echo a; echo b\;c");
+	/// let words: Vec<_> = PosixParser::new().semicolon(Semicolon::Marker).parse(&mut s).collect();
+	/// assert_eq!( words, ["echo", "a", ";", "echo", "b;c"] );
+	///
+	/// let mut s = String::from("echo a;echo b");
+	/// let mut words = PosixParser::new().semicolon(Semicolon::End).parse(&mut s);
+	/// assert_eq!( words.by_ref().collect::<Vec<_>>(), ["echo", "a"] );
+	/// assert_eq!( words.remainder(), "echo b" );
+	/// ```
+	pub fn semicolon(mut self, mode: Semicolon) -> Self {
+		self.options.semicolon = mode;
+		self
+	}
+
+	/// End iteration at a word that is exactly `token` (unquoted and unescaped), leaving the rest of the input in
+	/// [PosixShellWords::remainder]
+	///
+	/// ```
+	/// let mut s = String::from("a b END c d");
+	/// let mut words = ::cmdline_words_parser::PosixParser::new().stop_token(b"END").parse(&mut s);
+	/// assert_eq!( words.by_ref().collect::<Vec<_>>(), ["a", "b"] );
+	/// assert_eq!( words.remainder(), "c d" );
+	/// ```
+	///
+	/// The token is copied into the parser, so it can come from runtime input.
+	///
+	/// # Panics
+	/// If `token` is longer than [MAX_STOP_TOKEN_LEN] bytes
+	pub fn stop_token(mut self, token: &[u8]) -> Self {
+		assert!(token.len() <= MAX_STOP_TOKEN_LEN, "stop token is longer than {} bytes", MAX_STOP_TOKEN_LEN);
+		let mut bytes = [0; MAX_STOP_TOKEN_LEN];
+		bytes[..token.len()].copy_from_slice(token);
+		self.options.stop_token = Some(StopToken { bytes, len: token.len() as u8 });
+		self
+	}
+
+	/// Parse a string using these options
+	pub fn parse<'a, T: ?Sized + ByteString>(&self, string: &'a mut T) -> PosixShellWords<'a, T::OutSlice, C> {
+		// SAFE: Should be ensuring correct (visible) UTF-8
+		PosixShellWords::new(unsafe { string.as_mut_bytes() }, *self)
+	}
+
+	/// Parse a string using these options, recording the parser's decisions into `trace` as words are parsed (for
+	/// debugging)
+	///
+	/// See [PosixShellWords::with_trace] and [crate::parse_posix_traced].
+	#[cfg(feature="alloc")]
+	pub fn parse_traced<'a, T: ?Sized + ByteString>(&self, string: &'a mut T, trace: &'a mut ::alloc::vec::Vec<TraceEvent>) -> PosixShellWords<'a, T::OutSlice, C> {
+		self.parse(string).with_trace(trace)
+	}
+
+	/// Record the decisions made when parsing all of `buf` with these options, without modifying it
+	///
+	/// A convenience that parses a copy of `buf`, use [PosixParser::parse_traced] to trace the words as they're used.
+	#[cfg(feature="alloc")]
+	pub fn trace(&self, buf: &[u8], trace: &mut ::alloc::vec::Vec<TraceEvent>) {
+		let mut copy = buf.to_vec();
+		let mut words = PosixShellWords::<[u8], C>::new(&mut copy, *self).with_trace(trace);
+		while words.next_word_result().is_some() {
+		}
+	}
+
+	/// Check that `buf` would parse without errors using these options, without modifying it
+	///
+	/// Returns the first error that [PosixShellWords::next_result] (or [PosixShellWords::errors]) would report, so
+	/// unterminated quotes are only errors with [Unterminated::Error]. Without the `alloc` feature this only checks for
+	/// unterminated quotes and escapes.
+	///
+	/// ```
+	/// use cmdline_words_parser::PosixParser;
+	/// use cmdline_words_parser::posix::{InvalidEscape, ParseError, Unterminated};
+	/// assert_eq!( PosixParser::new().validate(b"echo 'a b"), Ok(()) );
+	/// assert_eq!( PosixParser::new().on_unterminated(Unterminated::Error).validate(b"echo 'a b"), Err(ParseError::UnterminatedQuote) );
+	/// assert_eq!( PosixParser::new().on_invalid_escape(InvalidEscape::Recover).validate(br"echo \x"), Err(ParseError::InvalidEscape) );
+	/// ```
+	pub fn validate(&self, buf: &[u8]) -> Result<(), ParseError> {
+		#[cfg(feature="alloc")]
+		{
+			let mut copy = buf.to_vec();
+			let mut words = PosixShellWords::<[u8], C>::new(&mut copy, *self);
+			while let Some(rv) = words.next_word_result() {
+				rv?;
+			}
+			match words.errors().first()
+			{
+			Some(&e) => Err(e),
+			None => Ok(()),
+			}
+		}
+		#[cfg(not(feature="alloc"))]
+		{
+			let mut pos = 0;
+			let mut mode = PosixEscapeMode::Outer;
+			loop {
+				pos += self.separators_len(&buf[pos..]);
+				if pos == buf.len() {
+					break;
+				}
+				let (len, end_mode) = self.word_end(&buf[pos..]);
+				pos += len;
+				mode = end_mode;
+			}
+			match mode
+			{
+			PosixEscapeMode::Outer => Ok(()),
+			_ if self.options.on_unterminated != Unterminated::Error => Ok(()),
+			_ => Err(unterminated_error(mode)),
+			}
+		}
+	}
+
+	/// Single-byte state transition, applying the configured escaping options on top of [step]
+	#[inline]
+	fn step(&self, mode: PosixEscapeMode, byte: u8) -> (PosixEscapeMode, Emit) {
+		if self.options.on_invalid_escape == InvalidEscape::Keep && self.is_invalid_escape(mode, byte) {
+			return (step(mode, byte).0, Emit::two(b'\\', byte));
+		}
+		match mode
+		{
+		_ if self.options.raw => (PosixEscapeMode::Outer, Emit::one(byte)),
+		PosixEscapeMode::OuterSlash if byte == b';' && self.options.semicolon != Semicolon::Literal => (PosixEscapeMode::Outer, Emit::one(byte)),
+		PosixEscapeMode::OuterSlash if self.options.bash_escapes => (PosixEscapeMode::Outer, match byte
+			{
+			// Line continuation
+			b'\n' => Emit::NONE,
+			v => Emit::one(v),
+			}),
+		PosixEscapeMode::DoubleQuoteSlash if self.options.bash_double_quotes => (PosixEscapeMode::DoubleQuote, match byte
+			{
+			b'\n' => Emit::NONE,
+			v @ b'$' | v @ b'`' | v @ b'"' | v @ b'\\' => Emit::one(v),
+			// Anything else keeps the backslash
+			v => Emit::two(b'\\', v),
+			}),
+		PosixEscapeMode::SingleQuote if self.options.single_quote_verbatim => match byte
+			{
+			b'\'' => (PosixEscapeMode::Outer, Emit::NONE),
+			v => (mode, Emit::one(v)),
+			},
+		PosixEscapeMode::SingleQuoteSlash if self.options.single_quote_escapes => {
+			// Same escapes as within double quotes
+			let (_, out) = step(PosixEscapeMode::DoubleQuoteSlash, byte);
+			(PosixEscapeMode::SingleQuote, out.map_or(Emit::NONE, Emit::one))
+			},
+		_ => {
+			let (mode, out) = step(mode, byte);
+			(mode, out.map_or(Emit::NONE, Emit::one))
+			},
+		}
+	}
+
+	/// Check if `byte` is an unknown escape (which is dropped by default), see [PosixParser::on_invalid_escape]
+	fn is_invalid_escape(&self, mode: PosixEscapeMode, byte: u8) -> bool {
+		match mode
+		{
+		_ if self.options.raw => false,
+		PosixEscapeMode::OuterSlash if byte == b';' && self.options.semicolon != Semicolon::Literal => false,
+		// These modes handle every escape
+		PosixEscapeMode::OuterSlash if self.options.bash_escapes => false,
+		PosixEscapeMode::DoubleQuoteSlash if self.options.bash_double_quotes => false,
+		PosixEscapeMode::SingleQuoteSlash if self.options.single_quote_escapes => step(PosixEscapeMode::DoubleQuoteSlash, byte).1.is_none(),
+		PosixEscapeMode::OuterSlash | PosixEscapeMode::SingleQuoteSlash | PosixEscapeMode::DoubleQuoteSlash => step(mode, byte).1.is_none(),
+		_ => false,
+		}
+	}
+
+	/// Check for a `""` within double quotes, when [PosixParser::double_quote_as_literal] is enabled
+	#[inline]
+	fn is_doubled_quote(&self, mode: PosixEscapeMode, bytes: &[u8]) -> bool {
+		self.options.double_quote_as_literal && mode == PosixEscapeMode::DoubleQuote && bytes.starts_with(b"\"\"")
+	}
+
+	/// Get the length of the `$(...)` or `$((...))` at the start of `bytes`, when [PosixParser::substitutions] is
+	/// enabled (and the substitution is terminated)
+	fn substitution_len(&self, mode: PosixEscapeMode, bytes: &[u8]) -> Option<usize> {
+		if !self.options.substitutions || !matches!(mode, PosixEscapeMode::Outer | PosixEscapeMode::DoubleQuote) || !bytes.starts_with(b"$(") {
+			return None;
+		}
+		// Count nesting of (unquoted) parens, so `$((`/`))` is handled the same as `$(`/`)`
+		let mut inner = PosixEscapeMode::Outer;
+		let mut depth = 0;
+		for (i, &b) in bytes.iter().enumerate().skip(1)
+		{
+			if inner == PosixEscapeMode::Outer {
+				match b
+				{
+				b'(' => depth += 1,
+				b')' => {
+					depth -= 1;
+					if depth == 0 {
+						return Some(i + 1);
+					}
+					},
+				_ => {},
+				}
+			}
+			inner = step(inner, b).0;
+		}
+		None
+	}
+
+	/// Get the length of the run of separators (and ignored carriage returns) at the start of `bytes`
+	fn separators_len(&self, bytes: &[u8]) -> usize {
+		let mut pos = 0;
+		loop {
+			let len = match bytes.get(pos)
+				{
+				Some(&b'\r') if self.options.cr_mode == CrMode::Ignore => 1,
+				_ => self.separator_len(&bytes[pos..]),
+				};
+			if len == 0 {
+				break;
+			}
+			pos += len;
+		}
+		pos
+	}
+
+	/// Find the end of the word at the start of `bytes` (after any soft separator), without unescaping it
+	///
+	/// Also returns the escape mode at the end of the word.
+	fn word_end(&self, bytes: &[u8]) -> (usize, PosixEscapeMode) {
+		let mut mode = PosixEscapeMode::Outer;
+		let mut skip = 0;
+		for i in 0 .. bytes.len()
+		{
+			if skip > 0 {
+				skip -= 1;
+				continue;
+			}
+			let mut byte = bytes[i];
+			if mode == PosixEscapeMode::Outer && self.separator_len(&bytes[i..]) > 0 {
+				if let Some(sep) = self.options.soft_separator {
+					// Include whitespace followed by a soft separator, and trailing whitespace (in case a soft
+					// separator is appended later)
+					let ws = self.separators_len(&bytes[i..]);
+					match bytes.get(i + ws)
+					{
+					None => return (bytes.len(), mode),
+					Some(&b) if b == sep => return (i + ws + 1, mode),
+					_ => {},
+					}
+				}
+				return (i, mode);
+			}
+			if mode == PosixEscapeMode::Outer && Some(byte) == self.options.soft_separator {
+				return (i + 1, mode);
+			}
+			// A semicolon is a word on its own
+			if mode == PosixEscapeMode::Outer && byte == b';' && self.options.semicolon != Semicolon::Literal {
+				return (::std::cmp::max(i, 1), mode);
+			}
+			if self.options.map_smart_quotes && !self.options.raw {
+				if let Some(b) = smart_quote(&bytes[i..]) {
+					skip = 2;
+					byte = b;
+				}
+			}
+			if skip == 0 && self.is_doubled_quote(mode, &bytes[i..]) {
+				skip = 1;
+				continue;
+			}
+			if let Some(n) = self.substitution_len(mode, &bytes[i..]) {
+				skip = n - 1;
+				continue;
+			}
+			mode = self.step(mode, byte).0;
+		}
+		(bytes.len(), mode)
+	}
+
+	/// Get the length of the separator at the start of `bytes` (zero if there isn't one)
+	#[inline]
+	fn separator_len(&self, bytes: &[u8]) -> usize {
+		match bytes.first()
+		{
+		Some(&b'\r') if self.options.cr_mode != CrMode::Separator => 0,
+		Some(&b) if self.classifier.map_or(is_separator(b), |f| f(b)) => 1,
+		Some(&b'\\') if self.options.backslash_newline_separates && bytes.get(1) == Some(&b'\n') => 2,
+		Some(&b) if b >= 0x80 && self.options.unicode_whitespace => match decode_char(bytes)
+			{
+			Some((c, len)) if c.is_whitespace() => len,
+			_ => 0,
+			},
+		_ => 0,
+		}
+	}
+}
+
+/// Iterator yeilding unescaped strings in the standard POSIX shell format
+///
+/// - Splits arguments on whitespace (space, tab, newline, and carriage return)
+/// - Special character escapes (`\n`, `\t`, `\r`) for literal versions of control characters
+/// - Supports single and double-quoted strings
+///  - Single quoted strings only support single quote and backslash escaped (any other character is passed verbatim)
+///  - Double quoted strings support a full set of escaped special characters.
+/// - Interpreted characters can be escaped by prefixing with a backslash
+pub struct PosixShellWords<'a,T:?Sized+ByteStringSlice,C=fn(u8) -> bool>
+{
+	buf: &'a mut [u8],
+	opts: PosixParser<C>,
+	original_len: usize,
+	/// Escape mode at the end of the most recent word (non-`Outer` if it hit the end of input in a quote/escape)
+	end_mode: PosixEscapeMode,
+	/// Set once an error has been returned (or a stop token seen), after which no more words are yielded
+	done: bool,
+	/// Set if the most recent word had to be moved/unescaped within the buffer
+	last_copied: bool,
+	/// Set until the first word has been yielded/skipped
+	at_command: bool,
+	/// Text of the comment that ended iteration (if any)
+	comment: Option<&'a [u8]>,
+	/// Errors recorded with [InvalidEscape::Recover]
+	#[cfg(feature="alloc")]
+	errors: ::alloc::vec::Vec<ParseError>,
+	/// Where decisions are recorded, see [PosixShellWords::with_trace]
+	#[cfg(feature="alloc")]
+	trace: Option<&'a mut ::alloc::vec::Vec<TraceEvent>>,
+	_pd: ::std::marker::PhantomData<T>,
+}
+
+/// How a word was quoted in the input, see [Word::quote_style]
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
+pub enum QuoteStyle
+{
+	/// No quotes (escapes may still have been used)
+	None,
+	/// Only single quotes
+	Single,
+	/// Only double quotes
+	Double,
+	/// Both single and double quotes
+	Mixed,
+}
+impl QuoteStyle
+{
+	/// Update after another quoted region was seen
+	fn with(self, other: QuoteStyle) -> QuoteStyle {
+		match self
+		{
+		QuoteStyle::None => other,
+		_ if self == other => self,
+		_ => QuoteStyle::Mixed,
+		}
+	}
+}
+
+/// Reason that a string ended before all quotes/escapes were terminated
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
+pub enum Incomplete
+{
+	/// Ended within a single-quoted region
+	SingleQuote,
+	/// Ended within a double-quoted region
+	DoubleQuote,
+	/// Ended with a trailing (unquoted) backslash
+	Escape,
+}
+
+impl<'a, T: ?Sized + ByteStringSlice, C: Fn(u8) -> bool + Copy> PosixShellWords<'a, T, C>
+{
+	pub(crate) fn new(input_bytes: &mut [u8], opts: PosixParser<C>) -> PosixShellWords<'_, T, C> {
+		PosixShellWords {
+			original_len: input_bytes.len(),
+			buf: input_bytes,
+			opts,
+			end_mode: PosixEscapeMode::Outer,
+			done: false,
+			last_copied: false,
+			at_command: true,
+			comment: None,
+			#[cfg(feature="alloc")]
+			errors: ::alloc::vec::Vec::new(),
+			#[cfg(feature="alloc")]
+			trace: None,
+			_pd: ::std::marker::PhantomData::<T>,
+			}
+	}
+
+	/// Length (in bytes) of the string originally passed to the parser
+	pub fn original_len(&self) -> usize {
+		self.original_len
+	}
+	/// Number of bytes of the original string consumed so far (including trailing separators)
+	pub fn consumed(&self) -> usize {
+		self.original_len - self.buf.len()
+	}
+
+	/// Count the remaining words without modifying the buffer (used to size allocations)
+	#[cfg(feature="alloc")]
+	fn count_remaining(&self) -> usize {
+		let mut pos = 0;
+		let mut count = 0;
+		loop {
+			pos += self.opts.separators_len(&self.buf[pos..]);
+			if pos == self.buf.len() {
+				break count;
+			}
+			count += 1;
+			pos += self.opts.word_end(&self.buf[pos..]).0;
+		}
+	}
+
+	/// Record the parser's decisions into `trace` as the following words are parsed (for debugging)
+	///
+	/// Positions are offsets in the original input. Words that are skipped (e.g. with [PosixShellWords::skip_word])
+	/// aren't traced.
+	///
+	/// ```
+	/// use cmdline_words_parser::posix::TraceEvent;
+	/// let mut cmdline = String::from("a 'b'");
+	/// let mut trace = Vec::new();
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+	/// assert_eq!( words.next(), Some("a") );
+	/// let mut words = words.with_trace(&mut trace);
+	/// assert_eq!( words.next(), Some("b") );
+	/// assert_eq!( trace, [
+	///     TraceEvent::EnterQuote { pos: 2, quote: b'\'' },
+	///     TraceEvent::ExitQuote { pos: 4 },
+	///     TraceEvent::TokenBoundary { pos: 5 },
+	///     ] );
+	/// ```
+	#[cfg(feature="alloc")]
+	pub fn with_trace(mut self, trace: &'a mut ::alloc::vec::Vec<TraceEvent>) -> Self {
+		self.trace = Some(trace);
+		self
+	}
+
+	/// The unparsed remainder of the input (e.g. after a [stop token](PosixParser::stop_token))
+	pub fn remainder(&self) -> &T {
+		T::from_bytes(self.buf).expect("POSIX Word spliting caused UTF-8 inconsistency")
+	}
+	/// The text (after the `#`) of the [comment](PosixParser::comments) that ended iteration, if any
+	///
+	/// ```
+	/// let mut cmdline = String::from("run foo # do the thing\nnext");
+	/// let mut words = ::cmdline_words_parser::PosixParser::new().comments(true).parse(&mut cmdline);
+	/// assert_eq!( words.by_ref().collect::<Vec<_>>(), ["run", "foo"] );
+	/// assert_eq!( words.trailing_comment(), Some(" do the thing") );
+	/// assert_eq!( words.remainder(), "next" );
+	/// ```
+	pub fn trailing_comment(&self) -> Option<&T> {
+		self.comment.map(|c| T::from_bytes(c).expect("POSIX Word spliting caused UTF-8 inconsistency"))
+	}
+	/// Errors recorded while parsing (with [InvalidEscape::Recover]), in the order they were found
+	///
+	/// Only words that have been returned (not [skipped](PosixShellWords::skip_word)) are checked.
+	///
+	/// ```
+	/// use cmdline_words_parser::posix::{InvalidEscape, ParseError};
+	/// let mut s = String::from(r"a\x b");
+	/// let mut words = ::cmdline_words_parser::PosixParser::new().on_invalid_escape(InvalidEscape::Recover).parse(&mut s);
+	/// assert_eq!( words.by_ref().collect::<Vec<_>>(), ["a", "b"] );
+	/// assert_eq!( words.errors(), [ParseError::InvalidEscape] );
+	/// ```
+	#[cfg(feature="alloc")]
+	pub fn errors(&self) -> &[ParseError] {
+		&self.errors
+	}
+	/// Copy the unparsed remainder of the input, e.g. to parse it separately with different options
+	///
+	/// This allocates a copy, the iteration is not affected.
+	///
+	/// ```
+	/// let mut cmdline = String::from("a 'b c' d");
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+	/// words.next();
+	/// let mut rest = words.clone_remaining();
+	/// assert_eq!( rest, b"'b c' d" );
+	/// assert_eq!( ::cmdline_words_parser::parse_posix(&mut rest[..]).count(), 2 );
+	/// assert_eq!( words.next(), Some("b c") );
+	/// ```
+	#[cfg(feature="alloc")]
+	pub fn clone_remaining(&self) -> ::alloc::vec::Vec<u8> {
+		self.buf.to_vec()
+	}
+	/// Take the unparsed remainder of the input, e.g. to parse the next record after a stop token
+	pub fn into_remainder(self) -> &'a mut T {
+		T::from_bytes_mut(self.buf).expect("POSIX Word spliting caused UTF-8 inconsistency")
+	}
+
+	/// Check if the most recently returned word had to be moved within the buffer (e.g. due to quotes or escapes),
+	/// instead of being returned as it appeared in the input
+	///
+	/// ```
+	/// let mut cmdline = String::from(r"plain esc\ aped");
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+	/// assert_eq!( words.next(), Some("plain") );
+	/// assert!( !words.last_token_copied() );
+	/// assert_eq!( words.next(), Some("esc aped") );
+	/// assert!( words.last_token_copied() );
+	/// ```
+	pub fn last_token_copied(&self) -> bool {
+		self.last_copied
+	}
+
+	/// Check if the most recently returned word was cut off by the end of the input
+	///
+	/// Returns `None` if all quotes and escapes were terminated.
+	pub fn incomplete(&self) -> Option<Incomplete> {
+		match self.end_mode
+		{
+		PosixEscapeMode::Outer => None,
+		PosixEscapeMode::OuterSlash => Some(Incomplete::Escape),
+		PosixEscapeMode::SingleQuote | PosixEscapeMode::SingleQuoteSlash => Some(Incomplete::SingleQuote),
+		PosixEscapeMode::DoubleQuote | PosixEscapeMode::DoubleQuoteSlash => Some(Incomplete::DoubleQuote),
+		}
+	}
+}
+
+impl<'a, T: ?Sized + ByteStringSlice + 'a, C: Fn(u8) -> bool + Copy> PosixShellWords<'a, T, C>
+{
+	/// Write all remaining words to a formatter, separated by `sep`
+	///
+	/// Byte outputs are written lossily (invalid UTF-8 is replaced with U+FFFD)
+	///
+	/// ```
+	/// let mut cmdline = String::from(r"Hello\ World 'Second Argument'");
+	/// let mut out = String::new();
+	/// ::cmdline_words_parser::parse_posix(&mut cmdline).for_each_into(", ", &mut out).unwrap();
+	/// assert_eq!(out, "Hello World, Second Argument");
+	/// ```
+	pub fn for_each_into<W: ::std::fmt::Write>(self, sep: &str, out: &mut W) -> ::std::fmt::Result {
+		for (i, w) in self.enumerate() {
+			if i != 0 {
+				out.write_str(sep)?;
+			}
+			w.write_lossy(out)?;
+		}
+		Ok( () )
+	}
+}
+
+/// Parsed words with random access, see [PosixShellWords::collect_indexed]
+#[cfg(feature="alloc")]
+pub struct IndexedWords<'a, T: ?Sized + 'a>
+{
+	words: ::alloc::vec::Vec<&'a T>,
+	spans: ::alloc::vec::Vec<::std::ops::Range<usize>>,
+}
+#[cfg(feature="alloc")]
+impl<'a, T: ?Sized + 'a> IndexedWords<'a, T>
+{
+	/// Number of words
+	pub fn len(&self) -> usize {
+		self.words.len()
+	}
+	/// Returns true if there were no words
+	pub fn is_empty(&self) -> bool {
+		self.words.is_empty()
+	}
+	/// Get the `i`th word
+	pub fn get(&self, i: usize) -> Option<&'a T> {
+		self.words.get(i).copied()
+	}
+	/// Get the location of the `i`th (unescaped) word within the original buffer
+	pub fn span(&self, i: usize) -> Option<::std::ops::Range<usize>> {
+		self.spans.get(i).cloned()
+	}
+}
+#[cfg(feature="alloc")]
+impl<'a, T: ?Sized + ByteStringSlice + 'a, C: Fn(u8) -> bool + Copy> PosixShellWords<'a, T, C>
+{
+	/// Parse all remaining words, allowing random access to them
+	///
+	/// Only the word references and their spans are stored, the words themselves stay in the source buffer.
+	///
+	/// ```
+	/// let mut cmdline = String::from("mv 'old name' new");
+	/// let words = ::cmdline_words_parser::parse_posix(&mut cmdline).collect_indexed();
+	/// assert_eq!( words.len(), 3 );
+	/// assert_eq!( words.get(1), Some("old name") );
+	/// assert_eq!( words.span(1), Some(3..11) );
+	/// ```
+	pub fn collect_indexed(mut self) -> IndexedWords<'a, T> {
+		let mut rv = IndexedWords { words: ::alloc::vec::Vec::new(), spans: ::alloc::vec::Vec::new() };
+		loop {
+			// Skip leading separators first, so they're not included in the span
+			let skip = self.skip_separators(0);
+			split_off_front_inplace_mut(&mut self.buf, skip);
+			let start = self.consumed();
+			let base = self.buf.as_ptr() as usize;
+			let Some(w) = self.next_word() else { break };
+			// The word isn't always at the start of its region (e.g. with `trim_tokens`)
+			let start = start + (w.as_bytes().as_ptr() as usize - base);
+			rv.words.push(w.value());
+			rv.spans.push(start .. start + w.as_bytes().len());
+		}
+		rv
+	}
 }
 
-/// Iterator yeilding unescaped strings in the standard POSIX shell format
+#[cfg(feature="alloc")]
+impl<'a, C: Fn(u8) -> bool + Copy> PosixShellWords<'a, str, C>
+{
+	/// Collect the remaining words into owned strings
+	///
+	/// ```
+	/// let mut cmdline = String::from("a 'b c'");
+	/// assert_eq!( ::cmdline_words_parser::parse_posix(&mut cmdline).to_vec(), ["a", "b c"] );
+	/// ```
+	pub fn to_vec(self) -> ::alloc::vec::Vec<::alloc::string::String> {
+		let mut rv = ::alloc::vec::Vec::with_capacity(self.count_remaining());
+		rv.extend(self.map(|w| w.into()));
+		rv
+	}
+
+	/// Yield owned copies of the words
+	///
+	/// ```
+	/// let mut cmdline = String::from("a 'b c'");
+	/// let words: Vec<String> = ::cmdline_words_parser::parse_posix(&mut cmdline).map_owned().collect();
+	/// assert_eq!( words, ["a", "b c"] );
+	/// ```
+	pub fn map_owned(self) -> impl Iterator<Item=::alloc::string::String> + 'a
+	where
+		C: 'a
+	{
+		self.map(::alloc::string::String::from)
+	}
+
+	/// Collect the remaining words into owned strings, stopping at the first error
+	///
+	/// ```
+	/// use cmdline_words_parser::{PosixParser, posix::{Unterminated, ParseError}};
+	/// let parser = PosixParser::new().on_unterminated(Unterminated::Error);
+	/// let mut cmdline = String::from("a 'b c'");
+	/// assert_eq!( parser.parse(&mut cmdline).collect_result(), Ok(vec!["a".to_owned(), "b c".to_owned()]) );
+	/// let mut cmdline = String::from("a 'b c");
+	/// assert_eq!( parser.parse(&mut cmdline).collect_result(), Err(ParseError::UnterminatedQuote) );
+	/// ```
+	pub fn collect_result(mut self) -> Result<::alloc::vec::Vec<::alloc::string::String>, ParseError> {
+		let mut rv = ::alloc::vec::Vec::new();
+		while let Some(w) = self.next_result() {
+			rv.push(w?.into());
+		}
+		Ok(rv)
+	}
+}
+#[cfg(feature="std")]
+impl<'a, C: Fn(u8) -> bool + Copy> PosixShellWords<'a, str, C>
+{
+	/// Expand globs using a caller-provided expander
+	///
+	/// `expand` is called for each word that [has an unquoted glob](Word::has_glob), and the returned names are
+	/// yielded in place of that word. If it returns `None`, the word is yielded as-is.
+	///
+	/// ```
+	/// let mut cmdline = String::from("cat *.txt '*.md'");
+	/// let words: Vec<_> = ::cmdline_words_parser::parse_posix(&mut cmdline)
+	///     .expand_globs(|pat| if pat == "*.txt" { Some(vec!["a.txt".to_owned()]) } else { None })
+	///     .collect();
+	/// assert_eq!( words, ["cat", "a.txt", "*.md"] );
+	/// ```
+	pub fn expand_globs<F>(self, expand: F) -> ExpandGlobs<'a, F, C>
+	where
+		F: FnMut(&str) -> Option<::std::vec::Vec<::std::string::String>>
+	{
+		ExpandGlobs {
+			words: self,
+			expand,
+			pending: ::std::vec::Vec::new().into_iter(),
+		}
+	}
+}
+
+/// Iterator over words with globs expanded, see [PosixShellWords::expand_globs]
+#[cfg(feature="std")]
+pub struct ExpandGlobs<'a, F, C = fn(u8) -> bool>
+{
+	words: PosixShellWords<'a, str, C>,
+	expand: F,
+	pending: ::std::vec::IntoIter<::std::string::String>,
+}
+#[cfg(feature="std")]
+impl<'a, F, C> Iterator for ExpandGlobs<'a, F, C>
+where
+	F: FnMut(&str) -> Option<::std::vec::Vec<::std::string::String>>,
+	C: Fn(u8) -> bool + Copy,
+{
+	type Item = ::std::string::String;
+	fn next(&mut self) -> Option<::std::string::String> {
+		loop {
+			if let Some(v) = self.pending.next() {
+				return Some(v);
+			}
+			let w = self.words.next_word()?;
+			if w.has_glob() {
+				if let Some(names) = (self.expand)(w.value()) {
+					self.pending = names.into_iter();
+					continue;
+				}
+			}
+			return Some(w.value().into());
+		}
+	}
+}
+
+#[cfg(feature="alloc")]
+impl<'a, C: Fn(u8) -> bool + Copy> PosixShellWords<'a, [u8], C>
+{
+	/// Collect the remaining words into owned byte vectors
+	pub fn to_vec(self) -> ::alloc::vec::Vec<::alloc::vec::Vec<u8>> {
+		let mut rv = ::alloc::vec::Vec::with_capacity(self.count_remaining());
+		rv.extend(self.map(|w| w.into()));
+		rv
+	}
+}
+
+/// Unescape the entire string as if it were a single word (i.e. whitespace is kept, instead of splitting)
 ///
-/// - Splits arguments on whitespace (space, tab, newline, and carriage return)
-/// - Special character escapes (`\n`, `\t`, `\r`) for literal versions of control characters
-/// - Supports single and double-quoted strings
-///  - Single quoted strings only support single quote and backslash escaped (any other character is passed verbatim)
-///  - Double quoted strings support a full set of escaped special characters.
-/// - Interpreted characters can be escaped by prefixing with a backslash
-pub struct PosixShellWords<'a,T:?Sized+ByteStringSlice>(&'a mut [u8], ::std::marker::PhantomData<T>);
+/// ```
+/// let mut s = String::from(r"a\ b\ c 'and \'quoted\''");
+/// assert_eq!( ::cmdline_words_parser::posix::unescape_all(&mut s), "a b c and 'quoted'" );
+/// ```
+pub fn unescape_all<T: ?Sized + ByteString>(string: &mut T) -> &T::OutSlice
+{
+	// SAFE: Only ASCII bytes are removed, and the output is a subset of the input (so stays valid UTF-8)
+	let buf = unsafe { string.as_mut_bytes() };
+	let len = unescape_in_place(buf);
+	T::OutSlice::from_bytes(&buf[..len]).expect("POSIX unescape caused UTF-8 inconsistency")
+}
+
+/// Unescape the entire buffer as a single word (like [unescape_all]), returning the length of the result
+///
+/// The unescaped bytes are at the start of the buffer, the rest of the buffer is left in an unspecified state.
+///
+/// ```
+/// let mut buf = *br"a\ b";
+/// let len = ::cmdline_words_parser::posix::unescape_in_place(&mut buf);
+/// assert_eq!( &buf[..len], b"a b" );
+/// ```
+pub fn unescape_in_place(buf: &mut [u8]) -> usize
+{
+	let mut mode = PosixEscapeMode::Outer;
+	let mut outpos = 0;
+	for i in 0 .. buf.len()
+	{
+		let (new_mode, out) = step(mode, buf[i]);
+		mode = new_mode;
+		// Only ASCII can be dropped or replaced, so multi-byte characters are copied intact
+		debug_assert!(out == Some(buf[i]) || buf[i] < 0x80, "POSIX unescape changed a non-ASCII byte");
+		if let Some(b) = out {
+			if outpos != i {
+				buf[i] = 0;	// DEFENSIVE. Mangle string at read position to ensure no strays
+			}
+			buf[outpos] = b;
+			outpos += 1;
+		}
+	}
+	outpos
+}
+
+/// Parse a complete line, or report that more input is needed
+///
+/// Intended for REPLs: if the line ends inside a quote or after a trailing backslash, the caller should read another
+/// line, append it, and try again.
+///
+/// ```
+/// use cmdline_words_parser::posix::{try_complete, Incomplete};
+/// assert_eq!( try_complete(&mut String::from("echo 'a b'")), Ok(vec!["echo".to_owned(), "a b".to_owned()]) );
+/// assert_eq!( try_complete(&mut String::from("echo 'a b")), Err(Incomplete::SingleQuote) );
+/// ```
+#[cfg(feature="alloc")]
+pub fn try_complete(buf: &mut str) -> Result<::alloc::vec::Vec<::alloc::string::String>, Incomplete>
+{
+	let mut words = crate::parse_posix(buf);
+	let mut rv = ::alloc::vec::Vec::new();
+	for w in words.by_ref() {
+		rv.push(w.into());
+	}
+	match words.incomplete()
+	{
+	Some(i) => Err(i),
+	None => Ok(rv),
+	}
+}
+
+/// Check that `buf` would parse without errors (with [Unterminated::Error]), without modifying it
+///
+/// Uses the default rules, see [PosixParser::validate] to check against a configured parser.
+///
+/// ```
+/// use cmdline_words_parser::posix::{validate, ParseError};
+/// assert_eq!( validate(b"echo 'a b'"), Ok(()) );
+/// assert_eq!( validate(b"echo 'a b"), Err(ParseError::UnterminatedQuote) );
+/// ```
+pub fn validate(buf: &[u8]) -> Result<(), ParseError>
+{
+	PosixParser::new().on_unterminated(Unterminated::Error).validate(buf)
+}
+
+/// Parse exactly `N` words
+///
+/// ```
+/// let mut cmdline = String::from("'old name' new");
+/// let [src, dst] = ::cmdline_words_parser::posix::parse_fixed(&mut cmdline).unwrap();
+/// assert_eq!( (src, dst), ("old name", "new") );
+/// ```
+pub fn parse_fixed<T: ?Sized + ByteString, const N: usize>(buf: &mut T) -> Result<[&T::OutSlice; N], ArityError>
+{
+	let mut words = crate::parse_posix(buf);
+	let mut rv = [None; N];
+	let mut count = 0;
+	for w in words.by_ref().take(N) {
+		rv[count] = Some(w);
+		count += 1;
+	}
+	if count < N {
+		return Err(ArityError { expected: N, actual: count });
+	}
+	let extra = words.count();
+	if extra > 0 {
+		return Err(ArityError { expected: N, actual: N + extra });
+	}
+	Ok(rv.map(|w| w.expect("All words populated")))
+}
+
+/// Unescape the first word, and return it along with the (unparsed) rest of the input
+///
+/// The rest is `None` if there was only one word. If the input has no words, the first word is empty.
+///
+/// ```
+/// let mut cmdline = String::from(r"'my cmd' the \ rest");
+/// assert_eq!( ::cmdline_words_parser::posix::split_once(&mut cmdline), ("my cmd", Some(r"the \ rest")) );
+/// ```
+pub fn split_once<T: ?Sized + ByteString>(buf: &mut T) -> (&T::OutSlice, Option<&T::OutSlice>)
+{
+	let mut words = crate::parse_posix(buf);
+	let first = match words.next()
+		{
+		Some(w) => w,
+		None => T::OutSlice::from_bytes(&[]).expect("Empty string is always valid"),
+		};
+	let rest = words.into_remainder();
+	(first, if ::std::mem::size_of_val(rest) == 0 { None } else { Some(rest) })
+}
+
+/// Split the input into lines (on newlines outside of quotes/escapes), and parse each line separately
+///
+/// ```
+/// let mut script = String::from("echo 'a\nb'\nls -l\n");
+/// let lines: Vec<Vec<&str>> = ::cmdline_words_parser::posix::parse_lines(&mut script).map(|l| l.collect()).collect();
+/// assert_eq!( lines, [vec!["echo", "a\nb"], vec!["ls", "-l"]] );
+/// ```
+pub fn parse_lines<T: ?Sized + ByteString>(buf: &mut T) -> Lines<'_, T::OutSlice>
+{
+	Lines {
+		// SAFE: Only splits on an ASCII newline, and each line is parsed in a UTF-8 preserving way
+		buf: unsafe { buf.as_mut_bytes() },
+		_pd: ::std::marker::PhantomData,
+	}
+}
+
+/// Iterator over lines of input, see [parse_lines]
+pub struct Lines<'a, T: ?Sized + ByteStringSlice>
+{
+	buf: &'a mut [u8],
+	_pd: ::std::marker::PhantomData<T>,
+}
+impl<'a, T: ?Sized + ByteStringSlice + 'a> Iterator for Lines<'a, T>
+{
+	type Item = PosixShellWords<'a, T>;
+	fn next(&mut self) -> Option<PosixShellWords<'a, T>> {
+		if self.buf.is_empty() {
+			return None;
+		}
+		// Find the first newline that isn't quoted or escaped
+		let mut mode = PosixEscapeMode::Outer;
+		let mut end = self.buf.len();
+		for (i, &b) in self.buf.iter().enumerate()
+		{
+			if b == b'\n' && mode == PosixEscapeMode::Outer {
+				end = i;
+				break;
+			}
+			mode = PosixParser::new().step(mode, b).0;
+		}
+		let line = split_off_front_inplace_mut(&mut self.buf, end);
+		// Drop the newline itself (if not at the end of input)
+		if !self.buf.is_empty() {
+			split_off_front_inplace_mut(&mut self.buf, 1);
+		}
+		Some(PosixShellWords::new(line, PosixParser::new()))
+	}
+}
+
+/// Parse `s`, keep only the words that `pred` returns true for, and replace `s` with those words re-joined
+///
+/// Words that contain no special characters are written as-is, the rest are [quoted](Quoted).
+///
+/// ```
+/// let mut cmdline = String::from("rm -v 'my file' -f");
+/// ::cmdline_words_parser::posix::retain_words(&mut cmdline, |w| w != "-v");
+/// assert_eq!( cmdline, "rm 'my file' -f" );
+/// ```
+#[cfg(feature="alloc")]
+pub fn retain_words<F>(s: &mut ::alloc::string::String, mut pred: F)
+where
+	F: FnMut(&str) -> bool
+{
+	let mut rv = ::alloc::string::String::with_capacity(s.len());
+	for w in crate::parse_posix(&mut s[..]).filter(|w| pred(w)) {
+		if !rv.is_empty() {
+			rv.push(' ');
+		}
+		push_word(&mut rv, w);
+	}
+	*s = rv;
+}
+
+/// Replace the `index`th word of `s` with `new` (quoted if needed), leaving the rest of `s` unchanged
+///
+/// Returns false (without changing `s`) if there are not enough words.
+///
+/// ```
+/// let mut cmdline = String::from("cp  'a b' c");
+/// assert!( ::cmdline_words_parser::posix::replace_word(&mut cmdline, 2, "new dir") );
+/// assert_eq!( cmdline, "cp  'a b' 'new dir'" );
+/// ```
+#[cfg(feature="alloc")]
+pub fn replace_word(s: &mut ::alloc::string::String, index: usize, new: &str) -> bool
+{
+	let Some(range) = token_ranges(s.as_bytes()).nth(index) else { return false };
+	let mut word = ::alloc::string::String::new();
+	push_word(&mut word, new);
+	s.replace_range(range, &word);
+	true
+}
+
+/// Append `w` to `out`, quoting it only if it contains special characters
+#[cfg(feature="alloc")]
+fn push_word(out: &mut ::alloc::string::String, w: &str)
+{
+	use std::fmt::Write;
+	if !w.is_empty() && !w.bytes().any(|b| is_posix_special(b) || !b.is_ascii_graphic()) {
+		out.push_str(w);
+	}
+	else {
+		write!(out, "{}", Quoted(w)).expect("Writing to a String cannot fail");
+	}
+}
+
+/// Parse `line` into a C-style `argv` (e.g. for `execv`)
+///
+/// Returns the words as `CString`s, and a null-terminated array of pointers to them. The pointers are only valid
+/// while the first `Vec` is alive (and unmodified). Words containing a NUL byte are rejected.
+///
+/// ```
+/// let (args, argv) = ::cmdline_words_parser::posix::to_argv("ls -la").unwrap();
+/// assert_eq!( args.len(), 2 );
+/// assert_eq!( argv.len(), 3 );
+/// assert!( argv[2].is_null() );
+/// ```
+#[cfg(feature="std")]
+pub fn to_argv(line: &str) -> Result<(::std::vec::Vec<::std::ffi::CString>, ::std::vec::Vec<*const ::std::os::raw::c_char>), ::std::ffi::NulError>
+{
+	let mut scratch = ::std::string::String::from(line);
+	let args = crate::parse_posix(&mut scratch[..])
+		.map(::std::ffi::CString::new)
+		.collect::<Result<::std::vec::Vec<_>, _>>()?;
+	let argv = args.iter().map(|a| a.as_ptr()).chain(::std::iter::once(::std::ptr::null())).collect();
+	Ok((args, argv))
+}
+
+/// Parse `line` into owned `OsString`s, e.g. for an argument parser's `*_from` methods
+///
+/// If `argv0` is set, it's inserted as the first argument (as argument parsers usually expect the program name first).
+///
+/// ```
+/// let args = ::cmdline_words_parser::posix::to_os_args("--verbose 'in put.txt'", Some("prog"));
+/// assert_eq!( args, ["prog", "--verbose", "in put.txt"] );
+/// ```
+#[cfg(feature="std")]
+pub fn to_os_args(line: &str, argv0: Option<&str>) -> ::std::vec::Vec<::std::ffi::OsString>
+{
+	let mut scratch = ::std::string::String::from(line);
+	argv0.into_iter()
+		.chain(crate::parse_posix(&mut scratch[..]))
+		.map(::std::ffi::OsString::from)
+		.collect()
+}
+
+/// A parsed word, along with metadata about how it appeared in the source
+pub struct Word<'a, T: ?Sized + 'a>
+{
+	value: &'a T,
+	has_slash: bool,
+	trailing_separator: Option<u8>,
+	bytes: &'a [u8],
+	var_refs: u64,
+	has_glob: bool,
+	quote_style: QuoteStyle,
+	has_escape: bool,
+	terminator: Option<u8>,
+	is_semicolon: bool,
+}
+impl<'a, T: ?Sized + 'a> Word<'a, T>
+{
+	/// The unescaped word
+	pub fn value(&self) -> &'a T {
+		self.value
+	}
+	/// The unescaped word as bytes
+	pub fn as_bytes(&self) -> &'a [u8] {
+		self.bytes
+	}
+	/// The unescaped word as a string, if it's valid UTF-8 (checked on each call)
+	///
+	/// ```
+	/// let mut cmdline = *b"text \xFF";
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline[..]);
+	/// assert_eq!( words.next_word().unwrap().as_str(), Some("text") );
+	/// let w = words.next_word().unwrap();
+	/// assert_eq!( (w.as_bytes(), w.as_str()), (&b"\xFF"[..], None) );
+	/// ```
+	pub fn as_str(&self) -> Option<&'a str> {
+		::std::str::from_utf8(self.bytes).ok()
+	}
+	/// Returns true if the word contains a `/` (quoted or not), i.e. if it's a path instead of a command name to be
+	/// looked up in `PATH` (only meaningful for the first word)
+	pub fn looks_like_path(&self) -> bool {
+		self.has_slash
+	}
+	/// The [soft separator](PosixParser::soft_separator) that ended this word, if any
+	pub fn trailing_separator(&self) -> Option<u8> {
+		self.trailing_separator
+	}
+	/// Returns true if the word contains an unquoted (and unescaped) glob metacharacter (`*`, `?`, or `[`)
+	///
+	/// ```
+	/// let mut cmdline = String::from(r#"*.txt '*.txt' "*".txt"#);
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+	/// assert!( words.next_word().unwrap().has_glob() );
+	/// assert!( !words.next_word().unwrap().has_glob() );
+	/// assert!( !words.next_word().unwrap().has_glob() );
+	/// ```
+	pub fn has_glob(&self) -> bool {
+		self.has_glob
+	}
+	/// The byte that ended the word (e.g. `b' '` or `b'\n'`), or `None` if it ran to the end of the input
+	///
+	/// For a multi-byte separator (e.g. with [PosixParser::unicode_whitespace]) this is the first byte.
+	///
+	/// ```
+	/// let mut cmdline = String::from("a\tb\nc");
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+	/// assert_eq!( words.next_word().unwrap().terminator(), Some(b'\t') );
+	/// assert_eq!( words.next_word().unwrap().terminator(), Some(b'\n') );
+	/// assert_eq!( words.next_word().unwrap().terminator(), None );
+	/// ```
+	pub fn terminator(&self) -> Option<u8> {
+		self.terminator
+	}
+	/// Which kinds of quotes were used in the word
+	///
+	/// ```
+	/// use cmdline_words_parser::posix::QuoteStyle;
+	/// let mut cmdline = String::from(r#"plain 'single' a"b"c a'b'"c""#);
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+	/// assert_eq!( words.next_word().unwrap().quote_style(), QuoteStyle::None );
+	/// assert_eq!( words.next_word().unwrap().quote_style(), QuoteStyle::Single );
+	/// assert_eq!( words.next_word().unwrap().quote_style(), QuoteStyle::Double );
+	/// assert_eq!( words.next_word().unwrap().quote_style(), QuoteStyle::Mixed );
+	/// ```
+	pub fn quote_style(&self) -> QuoteStyle {
+		self.quote_style
+	}
+	/// Returns true if a backslash escape was used in the word (quoted or not)
+	pub fn has_escape(&self) -> bool {
+		self.has_escape
+	}
+	/// Returns true if this is an unquoted `;` from [Semicolon::Marker], instead of a word containing `;`
+	pub fn is_semicolon(&self) -> bool {
+		self.is_semicolon
+	}
+	/// Returns true if the word contains an ANSI escape sequence (`ESC [`, e.g. a terminal colour code)
+	///
+	/// ```
+	/// let mut cmdline = String::from("echo \x1b[31mred\x1b[0m");
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+	/// assert!( !words.next_word().unwrap().contains_ansi() );
+	/// assert!( words.next_word().unwrap().contains_ansi() );
+	/// ```
+	pub fn contains_ansi(&self) -> bool {
+		self.bytes.windows(2).any(|w| w == b"\x1b[")
+	}
+	/// Names of the variables referenced (as `$NAME` or `${NAME}`) by this word, where expansion would happen
+	///
+	/// References within single quotes or with an escaped `$` are not reported. Only the first 64 `$`s in a word are
+	/// considered.
+	///
+	/// ```
+	/// let mut cmdline = String::from(r#"echo "${PATH}x" '$HOME'$USER"#);
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+	/// words.next();
+	/// assert_eq!( words.next_word().unwrap().variable_refs().collect::<Vec<_>>(), ["PATH"] );
+	/// assert_eq!( words.next_word().unwrap().variable_refs().collect::<Vec<_>>(), ["USER"] );
+	/// ```
+	pub fn variable_refs(&self) -> VariableRefs<'a> {
+		VariableRefs {
+			bytes: self.bytes,
+			pos: 0,
+			index: 0,
+			mask: self.var_refs,
+		}
+	}
+}
+
+/// An owned copy of a word and its metadata (e.g. for storing in a `HashSet`)
+///
+/// ```
+/// use cmdline_words_parser::posix::OwnedWord;
+/// let mut cmdline = String::from(r"'b' c\ d");
+/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+/// let w = OwnedWord::from(words.next_word().unwrap());
+/// assert_eq!( (w.value.as_str(), w.quoted, w.escaped), ("b", true, false) );
+/// let w = OwnedWord::from(words.next_word().unwrap());
+/// assert_eq!( (w.value.as_str(), w.quoted, w.escaped), ("c d", false, true) );
+/// ```
+#[cfg(feature="alloc")]
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub struct OwnedWord
+{
+	/// The unescaped word (invalid UTF-8 is replaced with U+FFFD)
+	pub value: ::alloc::string::String,
+	/// Quotes were used in the word
+	pub quoted: bool,
+	/// Backslash escapes were used in the word
+	pub escaped: bool,
+	/// The word is an operator (e.g. `|`) instead of a plain word, never set by the conversion from [Word]
+	pub is_operator: bool,
+}
+#[cfg(feature="alloc")]
+impl<'a, T: ?Sized + ByteStringSlice + 'a> From<Word<'a, T>> for OwnedWord
+{
+	fn from(w: Word<'a, T>) -> OwnedWord {
+		let mut value = ::alloc::string::String::new();
+		w.value.write_lossy(&mut value).expect("Writing to a String cannot fail");
+		OwnedWord {
+			value,
+			quoted: w.quote_style != QuoteStyle::None,
+			escaped: w.has_escape,
+			is_operator: false,
+		}
+	}
+}
 
-impl<'a, T: ?Sized + ByteStringSlice> PosixShellWords<'a, T>
+/// Iterator over variable names referenced by a word, see [Word::variable_refs]
+#[derive(Clone,Debug)]
+pub struct VariableRefs<'a>
+{
+	bytes: &'a [u8],
+	pos: usize,
+	/// Index of the next `$` in the word
+	index: u32,
+	mask: u64,
+}
+impl<'a> Iterator for VariableRefs<'a>
 {
-	pub(crate) fn new(input_bytes: &mut [u8]) -> PosixShellWords<T> {
-		PosixShellWords(input_bytes, ::std::marker::PhantomData::<T>)
+	type Item = &'a str;
+	fn next(&mut self) -> Option<&'a str> {
+		while let Some(ofs) = self.bytes[self.pos..].iter().position(|&b| b == b'$')
+		{
+			let start = self.pos + ofs + 1;
+			self.pos = start;
+			let index = self.index;
+			self.index += 1;
+			if index >= 64 || self.mask & (1 << index) == 0 {
+				continue;
+			}
+			let is_name_start = |b: u8| b == b'_' || b.is_ascii_alphabetic();
+			let name_len = |bytes: &[u8]| match bytes.first()
+				{
+				Some(&b) if is_name_start(b) => bytes.iter().position(|&b| !(b == b'_' || b.is_ascii_alphanumeric())).unwrap_or(bytes.len()),
+				_ => 0,
+				};
+			let rest = &self.bytes[start..];
+			let name = if rest.first() == Some(&b'{') {
+					let len = name_len(&rest[1..]);
+					if len == 0 || rest.get(1 + len) != Some(&b'}') {
+						continue;
+					}
+					&rest[1..][..len]
+				}
+				else {
+					let len = name_len(rest);
+					if len == 0 {
+						continue;
+					}
+					&rest[..len]
+				};
+			// SAFE: Names are only ASCII
+			return Some(unsafe { ::std::str::from_utf8_unchecked(name) });
+		}
+		None
 	}
 }
 
-impl<'a, T: ?Sized + ByteStringSlice + 'a> Iterator for PosixShellWords<'a, T>
+impl<'a, T: ?Sized + ByteStringSlice + 'a, C: Fn(u8) -> bool + Copy> Iterator for PosixShellWords<'a, T, C>
 {
 	type Item = &'a T;
 	fn next(&mut self) -> Option<&'a T> {
-		// 1. Check for an empty string, this means the end has been reached.
-		if self.0.len() == 0 {
+		self.next_word().map(|w| w.value)
+	}
+}
+
+impl<'a, T: ?Sized + ByteStringSlice + 'a, C: Fn(u8) -> bool + Copy> PosixShellWords<'a, T, C>
+{
+	/// Get the next word, along with its metadata
+	///
+	/// Errors (see [PosixShellWords::next_result]) are reported as the end of the words.
+	pub fn next_word(&mut self) -> Option<Word<'a, T>> {
+		self.next_word_result().and_then(|r| r.ok())
+	}
+
+	/// Get the next word using different options (which are kept for subsequent words)
+	///
+	/// Allows context-sensitive parsing, where earlier words change how later words are parsed.
+	///
+	/// ```
+	/// use cmdline_words_parser::PosixParser;
+	/// let mut cmdline = String::from(r"run --raw 'a b' c\d");
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+	/// assert_eq!( words.next(), Some("run") );
+	/// assert_eq!( words.next(), Some("--raw") );
+	/// assert_eq!( words.next_with_opts(&PosixParser::new().raw(true)), Some("'a") );
+	/// assert_eq!( words.collect::<Vec<_>>(), ["b'", r"c\d"] );
+	/// ```
+	pub fn next_with_opts(&mut self, opts: &PosixParser<C>) -> Option<&'a T> {
+		self.opts = *opts;
+		self.next()
+	}
+
+	/// Get the next word, or an error if the input was malformed (according to the configured options)
+	///
+	/// Returns `None` once all words are consumed, or after an error has been returned.
+	pub fn next_result(&mut self) -> Option<Result<&'a T, ParseError>> {
+		self.next_word_result().map(|r| r.map(|w| w.value))
+	}
+
+	/// Advance past the next word without unescaping it, returning false if there were no more words
+	///
+	/// ```
+	/// let mut cmdline = String::from(r"prog\ name 'first arg'");
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+	/// assert!( words.skip_word() );
+	/// assert_eq!( words.next(), Some("first arg") );
+	/// assert!( !words.skip_word() );
+	/// ```
+	pub fn skip_word(&mut self) -> bool {
+		if self.done {
+			return false;
+		}
+		let start = self.skip_separators(0);
+		split_off_front_inplace_mut(&mut self.buf, start);
+		if self.buf.is_empty() || self.is_partial() || self.check_stop_token() || self.check_comment() || self.check_semicolon() {
+			return false;
+		}
+		// Only find the end of the word (the skipped bytes are left untouched)
+		let (endpos, mode) = self.word_opts().word_end(self.buf);
+		self.at_command = self.opts.options.semicolon == Semicolon::Marker && self.buf[..endpos] == b";"[..];
+		self.end_mode = mode;
+		let endpos = self.skip_separators(endpos);
+		split_off_front_inplace_mut(&mut self.buf, endpos);
+		true
+	}
+
+	/// Remove and return the last word, leaving the earlier words to be iterated
+	///
+	/// The last word is the one before iteration would end (e.g. at a [stop token](PosixParser::stop_token)), anything
+	/// after that is left in the [remainder](PosixShellWords::remainder).
+	///
+	/// Errors (see [PosixShellWords::next_result]) are reported as there being no word.
+	///
+	/// ```
+	/// let mut cmdline = String::from("cmd -x -y 'file name.txt'");
+	/// let mut words = ::cmdline_words_parser::parse_posix(&mut cmdline);
+	/// assert_eq!( words.pop_word(), Some("file name.txt") );
+	/// assert_eq!( words.collect::<Vec<_>>(), ["cmd", "-x", "-y"] );
+	/// ```
+	pub fn pop_word(&mut self) -> Option<&'a T> {
+		if self.done {
+			return None;
+		}
+		// Scan forwards to find the start of the last word (quotes mean that it can't be found by scanning backwards)
+		let mut pos = 0;
+		let mut start = None;
+		let mut end = 0;
+		let mut opts = self.word_opts();
+		loop {
+			pos += opts.separators_len(&self.buf[pos..]);
+			if pos == self.buf.len() || (opts.options.comments && self.buf[pos] == b'#') {
+				break;
+			}
+			if opts.options.semicolon == Semicolon::End && self.buf[pos] == b';' {
+				break;
+			}
+			if self.stop_token_len(&self.buf[pos..]).is_some() {
+				break;
+			}
+			// The last word is also the command if there's only one
+			let is_command = start.is_none() && self.at_command;
+			start = Some((pos, is_command));
+			pos += opts.word_end(&self.buf[pos..]).0;
+			end = pos;
+			opts = self.opts;
+		}
+		let (start, is_command) = start.unwrap_or((end, false));
+		let buf = ::std::mem::take(&mut self.buf);
+		// Move anything after the word (e.g. a stop token and the remainder) to before it, so it's still iterated
+		buf[start..].rotate_left(end - start);
+		let len = buf.len() - (end - start);
+		let (front, back) = buf.split_at_mut(len);
+		self.buf = front;
+		let mut last = PosixShellWords::new(back, self.opts);
+		last.at_command = is_command;
+		last.next()
+	}
+
+	/// Options for the next word (the [command separator](PosixParser::command_separator) only applies to the first)
+	fn word_opts(&self) -> PosixParser<C> {
+		let mut opts = self.opts;
+		if self.at_command {
+			if let Some(sep) = self.opts.options.command_separator {
+				opts.options.soft_separator = Some(sep);
+			}
+			if self.opts.options.bare_command != BareCommand::Off {
+				opts.options.raw = true;
+			}
+		}
+		opts
+	}
+
+	/// If the next word is the [stop token](PosixParser::stop_token), consume it and end iteration
+	fn check_stop_token(&mut self) -> bool {
+		let mut end = match self.stop_token_len(self.buf)
+			{
+			Some(end) => end,
+			None => return false,
+			};
+		// Clear the entire token, so the remainder is still valid
+		for b in &mut self.buf[..end] {
+			*b = 0;
+		}
+		end = self.skip_separators(end);
+		split_off_front_inplace_mut(&mut self.buf, end);
+		self.done = true;
+		true
+	}
+
+	/// Get the (raw) length of the word at the start of `bytes` if it's the [stop token](PosixParser::stop_token)
+	fn stop_token_len(&self, bytes: &[u8]) -> Option<usize> {
+		let token = self.opts.options.stop_token?;
+		let token = token.as_slice();
+		let (end, _) = self.opts.word_end(bytes);
+		let mut raw_len = if end > 0 && Some(bytes[end-1]) == self.opts.options.soft_separator { end - 1 } else { end };
+		while raw_len > 0 && is_separator(bytes[raw_len-1]) {
+			raw_len -= 1;
+		}
+		if &bytes[..raw_len] == token { Some(end) } else { None }
+	}
+
+	/// Check if the next word reaches the end of the input, when the end isn't [final](PosixParser::eof_is_final)
+	///
+	/// This is checked before stop tokens, as more input could make the word different.
+	fn is_partial(&self) -> bool {
+		if self.opts.options.eof_is_final {
+			return false;
+		}
+		// A semicolon is a complete word by itself
+		if self.opts.options.semicolon != Semicolon::Literal && self.buf.first() == Some(&b';') {
+			return false;
+		}
+		// A comment ends at a newline, not at the end of a word (see `check_comment`)
+		if self.opts.options.comments && self.buf.first() == Some(&b'#') {
+			return false;
+		}
+		self.word_opts().word_end(self.buf).0 == self.buf.len()
+	}
+
+	/// If the next word starts with `#` (and [PosixParser::comments] is enabled), consume the rest of the line and
+	/// end iteration
+	///
+	/// If the end of the line might not have been received yet (see [PosixParser::eof_is_final]), nothing is consumed.
+	fn check_comment(&mut self) -> bool {
+		if !self.opts.options.comments || self.buf.first() != Some(&b'#') {
+			return false;
+		}
+		let len = match self.buf.iter().position(|&b| b == b'\n')
+			{
+			Some(len) => len,
+			// Wait for the rest of the comment (without ending iteration)
+			None if !self.opts.options.eof_is_final => return true,
+			None => self.buf.len(),
+			};
+		let comment = split_off_front_inplace_mut(&mut self.buf, len);
+		self.comment = Some(&comment[1..]);
+		// Also consume the newline, so the remainder is the next line
+		if !self.buf.is_empty() {
+			split_off_front_inplace_mut(&mut self.buf, 1);
+		}
+		self.done = true;
+		true
+	}
+
+	/// If the next word is a `;` (and [PosixParser::semicolon] is [Semicolon::End]), consume it and end iteration
+	fn check_semicolon(&mut self) -> bool {
+		if self.opts.options.semicolon != Semicolon::End || self.buf.first() != Some(&b';') {
+			return false;
+		}
+		self.buf[0] = 0;
+		let end = self.skip_separators(1);
+		split_off_front_inplace_mut(&mut self.buf, end);
+		self.done = true;
+		true
+	}
+
+	/// Consume a `;` ([Semicolon::Marker]) at the start of the buffer, returning it as a word
+	fn semicolon_marker(&mut self) -> Word<'a, T> {
+		let terminator = self.buf.get(1).cloned().filter(|_| self.opts.separator_len(&self.buf[1..]) > 0);
+		#[cfg(feature="alloc")]
+		if let Some(trace) = self.trace.as_mut() {
+			trace.push(TraceEvent::TokenBoundary { pos: self.original_len - self.buf.len() + 1 });
+		}
+		let end = self.skip_separators(1);
+		let front = &split_off_front_inplace_mut(&mut self.buf, end)[..1];
+		self.end_mode = PosixEscapeMode::Outer;
+		self.last_copied = false;
+		// The next word starts a new statement
+		self.at_command = true;
+		Word {
+			value: T::from_bytes(front).expect("POSIX Word spliting caused UTF-8 inconsistency"),
+			has_slash: false,
+			trailing_separator: None,
+			bytes: front,
+			var_refs: 0,
+			has_glob: false,
+			quote_style: QuoteStyle::None,
+			has_escape: false,
+			terminator,
+			is_semicolon: true,
+		}
+	}
+
+	/// Consume (and clear) the separators starting at `pos`, returning the position after them
+	fn skip_separators(&mut self, pos: usize) -> usize {
+		let len = self.opts.separators_len(&self.buf[pos..]);
+		for b in &mut self.buf[pos..][..len] {
+			*b = 0;
+		}
+		pos + len
+	}
+
+	/// Get the next word along with its metadata, or an error if the input was malformed
+	pub fn next_word_result(&mut self) -> Option<Result<Word<'a, T>, ParseError>> {
+		if self.done {
+			return None;
+		}
+		// 1. Skip leading separators, then check for an empty string (this means the end has been reached).
+		let start = self.skip_separators(0);
+		split_off_front_inplace_mut(&mut self.buf, start);
+		if self.buf.is_empty() || self.is_partial() || self.check_stop_token() || self.check_comment() || self.check_semicolon() {
 			// TODO: Error when waiting for a character?
 			return None;
 		}
+		if self.opts.options.semicolon == Semicolon::Marker && self.buf[0] == b';' {
+			return Some(Ok(self.semicolon_marker()));
+		}
+		
+		let opts = self.word_opts();
+		let check_bare = self.at_command && self.opts.options.bare_command == BareCommand::Error;
+		self.at_command = false;
+		// Offset of the word in the input (for tracing)
+		#[cfg(feature="alloc")]
+		let base = self.consumed();
 		
 		// 2. Iterate byte-wise along string until something special is hit
 		let mut outpos = 0;
-		let mut endpos = self.0.len();
+		let mut endpos = self.buf.len();
 		let mut mode = PosixEscapeMode::Outer;
-		for i in 0 .. self.0.len()
+		let mut skip = 0;
+		let mut too_long = false;
+		// Tracking for `keep_enclosing_quotes`
+		let mut enclosing_quote = None;
+		let mut quote_closes = 0;
+		let mut last_close_end = 0;
+		let mut trailing_separator = None;
+		// Whitespace between the end of the word and the soft separator
+		let mut sep_ws = 0;
+		let mut terminator = None;
+		// Bitmap of which `$`s in the output are variable references
+		let mut var_refs = 0u64;
+		let mut n_dollars = 0;
+		// Remaining bytes of a `$(...)` substitution, which are copied as-is
+		let mut verbatim = 0;
+		let mut has_glob = false;
+		let mut quote_style = QuoteStyle::None;
+		let mut has_escape = false;
+		let mut copied = false;
+		for i in 0 .. self.buf.len()
 		{
-			let byte = self.0[i];
-			let out = match mode
-				{
-				PosixEscapeMode::Outer => match byte
-					{
-					b' ' => { endpos = i; break; },
-					// TODO: Should tab/newline/return be breaks too?
-					b'\t' | b'\n' | b'\r' => { endpos = i; break; },
-					b'\\' => {
-						mode = PosixEscapeMode::OuterSlash;
-						None
-						},
-					b'\'' => {
-						mode = PosixEscapeMode::SingleQuote;
-						None
-						},
-					b'"' => {
-						mode = PosixEscapeMode::DoubleQuote;
-						None
-						},
-					v @ _ => Some(v),
-					},
-				PosixEscapeMode::OuterSlash => {
-					mode = PosixEscapeMode::Outer;
-					match byte
-					{
-					v @ b' ' => Some(v),
-					v @ b'\t' => Some(v),
-					v @ b'\n' => Some(v),
-					v @ b'\r' => Some(v),
-					v @ b'\'' => Some(v),
-					v @ b'\"' => Some(v),
-					v @ b'\\' => Some(v),
-					b'n' => Some(b'\n'),
-					b'r' => Some(b'\r'),
-					b't' => Some(b'\t'),
-					_ => None,	// TODO: What to to on an invalid escape?
-					}},
-				PosixEscapeMode::SingleQuote => match byte
-					{
-					b'\\' => {
-						mode = PosixEscapeMode::SingleQuoteSlash;
-						None
-						},
-					b'\'' => {
-						mode = PosixEscapeMode::Outer;
-						None
-						},
-					v @ _ => Some(v),
-					},
-				PosixEscapeMode::SingleQuoteSlash => {
-					mode = PosixEscapeMode::SingleQuote;
-					match byte
-					{
-					v @ b'\'' => Some(v),
-					v @ b'\\' => Some(v),
-					_ => None,	// TODO: What to to on an invalid escape?
-					}},
-				PosixEscapeMode::DoubleQuote => match byte
-					{
-					b'\\' => {
-						mode = PosixEscapeMode::DoubleQuoteSlash;
-						None
-						},
-					b'"' => {
-						mode = PosixEscapeMode::Outer;
-						None
-						},
-					v @ _ => Some(v),
-					},
-				PosixEscapeMode::DoubleQuoteSlash => {
-					mode = PosixEscapeMode::DoubleQuote;
-					match byte
-					{
-					v @ b'\'' => Some(v),
-					v @ b'\"' => Some(v),
-					v @ b'\\' => Some(v),
-					b'n' => Some(b'\n'),
-					b'r' => Some(b'\r'),
-					b't' => Some(b'\t'),
-					_ => None,	// TODO: What to to on an invalid escape?
-					}},
+			if skip > 0 {
+				skip -= 1;
+				continue;
+			}
+			let mut byte = self.buf[i];
+			if byte == b'\r' && opts.options.cr_mode == CrMode::Ignore {
+				continue;
+			}
+			let outer = mode == PosixEscapeMode::Outer && verbatim == 0;
+			if outer && opts.separator_len(&self.buf[i..]) > 0 {
+				endpos = i;
+				terminator = Some(byte);
+				if let Some(sep) = opts.options.soft_separator {
+					// Whitespace followed by a soft separator is a single delimiter
+					let ws = opts.separators_len(&self.buf[i..]);
+					if self.buf.get(i + ws) == Some(&sep) {
+						trailing_separator = Some(sep);
+						sep_ws = ws;
+					}
+				}
+				break;
+			}
+			if outer && Some(byte) == opts.options.soft_separator {
+				trailing_separator = Some(byte);
+				terminator = Some(byte);
+				endpos = i;
+				break;
+			}
+			if outer && byte == b';' && opts.options.semicolon != Semicolon::Literal {
+				// Left in the buffer, to be the next word (or end iteration)
+				terminator = Some(byte);
+				endpos = i;
+				break;
+			}
+			if outer && opts.options.max_token_len == usize::MAX {
+				// Fast path: Copy a run of plain bytes in one go
+				let high_special = opts.options.unicode_whitespace || opts.options.map_smart_quotes;
+				let mut n = scan::find_special(&self.buf[i..], high_special);
+				let semi = opts.options.semicolon != Semicolon::Literal;
+				if opts.options.soft_separator.is_some() || opts.options.substitutions || opts.classifier.is_some() || semi {
+					let (sep, subst, classifier) = (opts.options.soft_separator, opts.options.substitutions, opts.classifier);
+					n = self.buf[i..][..n].iter()
+						.position(|&b| Some(b) == sep || (subst && b == b'$') || classifier.is_some_and(|f| f(b)) || (semi && b == b';'))
+						.unwrap_or(n);
+				}
+				if n > 1 {
+					if outpos != i {
+						copied = true;
+						self.buf.copy_within(i .. i + n, outpos);
+						// DEFENSIVE. Mangle the part of the source that wasn't overwritten
+						for v in &mut self.buf[::std::cmp::max(i, outpos + n) .. i + n] {
+							*v = 0;
+						}
+					}
+					if opts.options.ascii_lowercase {
+						self.buf[outpos..][..n].make_ascii_lowercase();
+					}
+					for &b in &self.buf[outpos..][..n] {
+						has_glob |= is_glob(b);
+						if b == b'$' {
+							if n_dollars < 64 {
+								var_refs |= 1 << n_dollars;
+							}
+							n_dollars += 1;
+						}
+					}
+					outpos += n;
+					skip = n - 1;
+					continue;
+				}
+			}
+			if opts.options.map_smart_quotes && !opts.options.raw && verbatim == 0 {
+				if let Some(b) = smart_quote(&self.buf[i..]) {
+					// Clear the entire multi-byte sequence, the (ASCII) replacement will be written if emitted
+					for v in &mut self.buf[i..][..3] {
+						*v = 0;
+					}
+					skip = 2;
+					byte = b;
+				}
+			}
+			// Only bytes outside of quotes/escapes/substitutions can be glob metacharacters
+			let unquoted = mode == PosixEscapeMode::Outer && verbatim == 0;
+			let (new_mode, out) = if verbatim > 0 {
+					verbatim -= 1;
+					(mode, Emit::one(byte))
+				}
+				else if skip == 0 && opts.is_doubled_quote(mode, &self.buf[i..]) {
+					skip = 1;
+					(mode, Emit::one(b'"'))
+				}
+				else if let Some(n) = opts.substitution_len(mode, &self.buf[i..]) {
+					verbatim = n - 1;
+					(mode, Emit::one(byte))
+				}
+				else {
+					#[cfg(feature="alloc")]
+					if opts.options.on_invalid_escape == InvalidEscape::Recover && opts.is_invalid_escape(mode, byte) {
+						self.errors.push(ParseError::InvalidEscape);
+					}
+					opts.step(mode, byte)
 				};
-			if let Some(b) = out {
+			match (mode, new_mode)
+			{
+			(PosixEscapeMode::Outer, PosixEscapeMode::SingleQuote | PosixEscapeMode::DoubleQuote) if i == 0 => {
+				enclosing_quote = Some(byte);
+				},
+			(PosixEscapeMode::SingleQuote | PosixEscapeMode::DoubleQuote, PosixEscapeMode::Outer) => {
+				quote_closes += 1;
+				last_close_end = i + 1 + skip;
+				},
+			_ => {},
+			}
+			match (mode, new_mode)
+			{
+			(PosixEscapeMode::Outer, PosixEscapeMode::SingleQuote) => quote_style = quote_style.with(QuoteStyle::Single),
+			(PosixEscapeMode::Outer, PosixEscapeMode::DoubleQuote) => quote_style = quote_style.with(QuoteStyle::Double),
+			(_, PosixEscapeMode::OuterSlash | PosixEscapeMode::SingleQuoteSlash | PosixEscapeMode::DoubleQuoteSlash) => has_escape = true,
+			_ => {},
+			}
+			#[cfg(feature="alloc")]
+			if let Some(trace) = self.trace.as_mut() {
+				let pos = base + i;
+				match (mode, new_mode)
+				{
+				(PosixEscapeMode::OuterSlash | PosixEscapeMode::SingleQuoteSlash | PosixEscapeMode::DoubleQuoteSlash, _) =>
+					trace.push(TraceEvent::Escape { pos, input: byte, output: out.as_slice().last().copied() }),
+				(PosixEscapeMode::Outer, PosixEscapeMode::SingleQuote | PosixEscapeMode::DoubleQuote) =>
+					trace.push(TraceEvent::EnterQuote { pos, quote: byte }),
+				(PosixEscapeMode::SingleQuote | PosixEscapeMode::DoubleQuote, PosixEscapeMode::Outer) =>
+					trace.push(TraceEvent::ExitQuote { pos }),
+				_ => {},
+				}
+			}
+			// Only ASCII can be dropped or replaced, so multi-byte characters are copied intact (and the read position can
+			// be cleared)
+			debug_assert!(byte < 0x80 || out.as_slice().last() == Some(&byte), "POSIX Word spliting changed a non-ASCII byte");
+			// Only unescaped `$`s outside of single quotes are variable references
+			let expands = matches!(mode, PosixEscapeMode::Outer | PosixEscapeMode::DoubleQuote);
+			mode = new_mode;
+			for &b in out.as_slice()
+			{
+				if too_long || outpos == opts.options.max_token_len {
+					// Over the limit, drop the byte (and remove any partial UTF-8 sequence)
+					if !too_long && b & 0xC0 == 0x80 {
+						while outpos > 0 {
+							outpos -= 1;
+							let was_lead = self.buf[outpos] & 0xC0 != 0x80;
+							self.buf[outpos] = 0;
+							if was_lead {
+								break;
+							}
+						}
+					}
+					too_long = true;
+					self.buf[i] = 0;
+					continue;
+				}
 				if outpos != i {
+					copied = true;
 					assert!(outpos < i);
-					self.0[i] = 0;	// DEFENSIVE. Mangle string at read position to ensure no strays
-					self.0[outpos] = b;
+					self.buf[i] = 0;	// DEFENSIVE. Mangle string at read position to ensure no strays
 				}
+				has_glob |= unquoted && new_mode == PosixEscapeMode::Outer && is_glob(b);
+				if b == b'$' {
+					if expands && n_dollars < 64 {
+						var_refs |= 1 << n_dollars;
+					}
+					n_dollars += 1;
+				}
+				self.buf[outpos] = if opts.options.ascii_lowercase { b.to_ascii_lowercase() } else { b };
 				outpos += 1;
 			}
 		}
-		// Consume multiple separators
-		while endpos < self.0.len() && self.0[endpos] == b' ' {
-			self.0[endpos] = 0;
-			endpos += 1;
+		self.end_mode = mode;
+		self.last_copied = copied;
+		#[cfg(feature="alloc")]
+		if let Some(trace) = self.trace.as_mut() {
+			trace.push(TraceEvent::TokenBoundary { pos: base + endpos });
+		}
+		if opts.options.keep_enclosing_quotes && !too_long && quote_closes == 1 && last_close_end == endpos {
+			if let Some(q) = enclosing_quote {
+				// The word was entirely within one pair of quotes, so has space to put them back
+				self.buf.copy_within(0 .. outpos, 1);
+				self.last_copied = true;
+				self.buf[0] = q;
+				self.buf[outpos + 1] = q;
+				outpos += 2;
+			}
+		}
+		// Consume the soft separator (if any), and then multiple separators
+		if trailing_separator.is_some() {
+			for v in &mut self.buf[endpos..][..sep_ws + 1] {
+				*v = 0;
+			}
+			endpos += sep_ws + 1;
 		}
+		let endpos = self.skip_separators(endpos);
 		
-		let ret = &split_off_front_inplace_mut(&mut self.0, endpos)[..outpos];
-		Some( T::from_bytes(ret).expect("POSIX Word spliting caused UTF-8 inconsistency") )
+		let front = &split_off_front_inplace_mut(&mut self.buf, endpos)[..];
+		// The entire consumed region must still be valid (it's visible again once parsing is done), which also
+		// ensures that the split was on a character boundary
+		debug_assert!(T::from_bytes(front).is_some(), "POSIX Word spliting caused UTF-8 inconsistency");
+		let ret = if opts.options.trim_tokens { trim_ascii(&front[..outpos]) } else { &front[..outpos] };
+		if check_bare && ret.iter().any(|&b| matches!(b, b'\\' | b'\'' | b'"')) {
+			self.done = true;
+			return Some(Err(ParseError::QuotedCommand));
+		}
+		if too_long && opts.options.on_too_long == TooLong::Error {
+			self.done = true;
+			return Some(Err(ParseError::TokenTooLong));
+		}
+		if self.end_mode != PosixEscapeMode::Outer {
+			match opts.options.on_unterminated
+			{
+			Unterminated::Accept => {},
+			Unterminated::Error => {
+				self.done = true;
+				return Some(Err(unterminated_error(self.end_mode)));
+				},
+			Unterminated::Drop => return None,
+			}
+		}
+		Some(Ok(Word {
+			value: T::from_bytes(ret).expect("POSIX Word spliting caused UTF-8 inconsistency"),
+			has_slash: ret.contains(&b'/'),
+			trailing_separator,
+			bytes: ret,
+			var_refs,
+			has_glob,
+			quote_style,
+			has_escape,
+			terminator,
+			is_semicolon: false,
+			}))
 	}
 }
-
@@ -0,0 +1,34 @@
+use crate::split_off_front_inplace_mut;
+use crate::ByteStringSlice;
+use crate::posix::is_separator;
+
+/// Iterator yeilding whitespace-separated words, with no escape or quote processing
+///
+/// - Splits arguments on whitespace (space, tab, newline, and carriage return), with runs of whitespace treated as one
+/// - All other bytes (including `\`, `'`, and `"`) are passed verbatim
+/// - Never modifies the source string
+pub struct WhitespaceWords<'a,T:?Sized+ByteStringSlice>(&'a mut [u8], ::std::marker::PhantomData<T>);
+
+impl<'a, T: ?Sized + ByteStringSlice> WhitespaceWords<'a, T>
+{
+	pub(crate) fn new(input_bytes: &mut [u8]) -> WhitespaceWords<'_, T> {
+		WhitespaceWords(input_bytes, ::std::marker::PhantomData::<T>)
+	}
+}
+
+impl<'a, T: ?Sized + ByteStringSlice + 'a> Iterator for WhitespaceWords<'a, T>
+{
+	type Item = &'a T;
+	fn next(&mut self) -> Option<&'a T> {
+		// 1. Skip leading separators
+		let start = self.0.iter().position(|&b| !is_separator(b)).unwrap_or(self.0.len());
+		split_off_front_inplace_mut(&mut self.0, start);
+		if self.0.is_empty() {
+			return None;
+		}
+		// 2. Find the end of the word
+		let len = self.0.iter().position(|&b| is_separator(b)).unwrap_or(self.0.len());
+		let ret = split_off_front_inplace_mut(&mut self.0, len);
+		Some( T::from_bytes(ret).expect("Whitespace Word spliting caused UTF-8 inconsistency") )
+	}
+}
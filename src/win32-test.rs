@@ -0,0 +1,77 @@
+//!
+//! Tests for the Win32/cmd.exe parser
+//!
+use crate::parse_win32;
+#[cfg(not(feature="std"))]
+use alloc::{string::String, vec::Vec};
+
+#[test]
+fn switches()
+{
+	let mut s = String::from(r#"dir /s "/q" C:/path"#);
+	let mut iter = parse_win32(&mut s);
+	let w = iter.next_word().unwrap();
+	assert_eq!(w.value(), "dir");
+	assert!(!w.is_win_switch());
+	let w = iter.next_word().unwrap();
+	assert_eq!(w.value(), "/s");
+	assert!(w.is_win_switch());
+	let w = iter.next_word().unwrap();
+	assert_eq!(w.value(), "/q");
+	assert!(!w.is_win_switch());
+	let w = iter.next_word().unwrap();
+	assert_eq!(w.value(), "C:/path");
+	assert!(!w.is_win_switch());
+	assert!(iter.next_word().is_none());
+
+	// Leading whitespace before a switch
+	let mut s = String::from("  /s\t /q");
+	let mut iter = parse_win32(&mut s);
+	let w = iter.next_word().unwrap();
+	assert_eq!(w.value(), "/s");
+	assert!(w.is_win_switch());
+	assert!(iter.next_word().unwrap().is_win_switch());
+	assert!(iter.next_word().is_none());
+}
+
+#[test]
+fn caret_escape()
+{
+	let mut s = String::from("a^ b c\tx^");
+	let mut iter = parse_win32(&mut s);
+	assert_eq!(iter.next(), Some("a b"));
+	assert_eq!(iter.next(), Some("c"));
+	assert_eq!(iter.next(), Some("x"));
+	assert_eq!(iter.next(), None);
+
+	// Escaping a non-ASCII character only escapes that character
+	let mut s = String::from("^\u{e9} b");
+	let mut iter = parse_win32(&mut s);
+	assert_eq!(iter.next(), Some("\u{e9}"));
+	assert_eq!(iter.next(), Some("b"));
+	assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn win32_dialect_matches()
+{
+	let mut a = String::from("a^ b \"c d\" e\tf");
+	let mut b = a.clone();
+	let direct: Vec<&str> = parse_win32(&mut a).collect();
+	let dialect: Vec<&str> = crate::parse(super::Win32Dialect, &mut b).collect();
+	assert_eq!(direct, ["a b", "c d", "e", "f"]);
+	assert_eq!(direct, dialect);
+}
+
+#[test]
+fn paths()
+{
+	let mut s = String::from(r#"copy C:\a\b D:\c "C:\Program Files\x" \\server\share\"#);
+	let mut iter = parse_win32(&mut s);
+	assert_eq!(iter.next(), Some("copy"));
+	assert_eq!(iter.next(), Some(r"C:\a\b"));
+	assert_eq!(iter.next(), Some(r"D:\c"));
+	assert_eq!(iter.next(), Some(r"C:\Program Files\x"));
+	assert_eq!(iter.next(), Some(r"\\server\share\"));
+	assert_eq!(iter.next(), None);
+}
@@ -1,95 +1,110 @@
-use crate::split_off_front_inplace_mut;
 use crate::ByteStringSlice;
+use crate::dialect::{Dialect, DialectWords, Action};
+
+#[cfg(test)]
+#[path="win32-test.rs"]
+mod test;
 
 /// Iterator yeilding unescaped strings parsed in Win32 (cmd.exe) format
 ///
 /// - Splits arguments on whitespace (space, tab, newline, and carriage return)
 /// - A quote enters "quote mode", ended via either EOL or another " (closing quote cannot be escaped)
 /// - '^' escapes everything
-pub struct Win32ShellWords<'a,T:?Sized+ByteStringSlice>(&'a mut [u8], ::std::marker::PhantomData<T>);
+/// - Backslash and `:` are literal (path) characters, e.g. `C:\Users\me` is returned unchanged
+pub struct Win32ShellWords<'a,T:?Sized+ByteStringSlice>(DialectWords<'a, Win32Dialect, T>);
 
 impl<'a, T: ?Sized + ByteStringSlice> Win32ShellWords<'a, T>
 {
-	pub(crate) fn new(input_bytes: &mut [u8]) -> Win32ShellWords<T> {
-		Win32ShellWords(input_bytes, ::std::marker::PhantomData::<T>)
+	pub(crate) fn new(input_bytes: &mut [u8]) -> Win32ShellWords<'_, T> {
+		Win32ShellWords(DialectWords::new(input_bytes, Win32Dialect))
 	}
 }
-impl<'a, T: ?Sized + ByteStringSlice + 'a> Iterator for Win32ShellWords<'a, T>
+
+/// Escaping rules for cmd.exe, as used by [Win32ShellWords]
+#[derive(Copy,Clone,Debug,Default)]
+pub struct Win32Dialect;
+
+/// Opaque state for [Win32Dialect]
+#[derive(Copy,Clone,Debug,Default)]
+pub struct Win32State(State);
+
+#[derive(Copy,Clone,Debug,Default)]
+enum State {
+	#[default]
+	Normal,
+	Quote,
+	Escape,
+}
+
+impl Dialect for Win32Dialect
 {
-	type Item = &'a T;
-	fn next(&mut self) -> Option<&'a T> {
-		// 1. Check for an empty string, this means the end has been reached.
-		if self.0.len() == 0 {
-			// TODO: Error when waiting for a character?
-			return None;
-		}
+	type State = Win32State;
 
-		enum State {
-			Normal,
-			Quote,
-			Escape,
-		}
-		let mut mode = State::Normal;
-		// 2. Iterate byte-wise along string until something special is hit
-		let mut outpos = 0;
-		let mut endpos = self.0.len();
-		for i in 0 .. self.0.len()
-		{
-			let byte = self.0[i];
-			let out = match mode
+	fn step(&self, state: Win32State, byte: u8) -> (Win32State, Action) {
+		let (state, action) = match state.0
+			{
+			State::Normal => match byte
+				{
+				b' ' | b'\t' | b'\n' | b'\r' => (State::Normal, Action::Break),
+				b'^' => (State::Escape, Action::Skip),
+				b'"' => (State::Quote, Action::Skip),
+				_ => (State::Normal, Action::Continue),
+				},
+			State::Quote => match byte
+				{
+				// <LF> ends the quote early (and terminates the current token)
+				b'\n' => (State::Normal, Action::Break),
+				// A quote ends the quote mode and switches back to normal
+				b'"' => (State::Normal, Action::Skip),
+				_ => (State::Quote, Action::Continue),
+				},
+			State::Escape => match byte
 				{
-				State::Normal => match byte
-					{
-					// TODO: Consume separators
-					b' ' => { endpos = i; break; },
-					b'\t' | b'\n' | b'\r' => { endpos = i; break; },
-					b'^' => {
-						mode = State::Escape;
-						continue
-						},
-					b'"' => {
-						mode = State::Quote;
-						continue
-						},
-					v @ _ => v,
-					},
-				State::Quote => match byte
-					{
-					// <LF> ends the quote early (and terminates the current token)
-					b'\n' => { endpos = i; break; },
-					// A quote ends the quote mode and switches back to normal
-					b'"' => {
-						mode = State::Normal;
-						continue
-						},
-					v @ _ => v,
-					},
-				State::Escape => match byte
-					{
-					// <LF> can't be escaped
-					b'\n' => { endpos = i-1; break; },
-					v @ _ => v,
-					},
-				};
+				// <LF> can't be escaped
+				b'\n' => (State::Normal, Action::Break),
+				_ => (State::Normal, Action::Continue),
+				},
+			};
+		(Win32State(state), action)
+	}
+
+	fn is_separator(&self, byte: u8) -> bool {
+		matches!(byte, b' ' | b'\t' | b'\n' | b'\r')
+	}
+}
 
-			if outpos != i {
-				assert!(outpos < i);
-				self.0[i] = 0;	// DEFENSIVE: Zero out string at read position to ensure that there's no invalid UTF-8
-				self.0[outpos] = out;
-			}
-			else {
-				// Input = output, don't change
-			}
-			outpos += 1;
-		}
+/// A parsed word, along with metadata about how it appeared in the source
+pub struct Word<'a, T: ?Sized + 'a>
+{
+	value: &'a T,
+	switch: bool,
+}
+impl<'a, T: ?Sized + 'a> Word<'a, T>
+{
+	/// The unescaped word
+	pub fn value(&self) -> &'a T {
+		self.value
+	}
+	/// Returns true if the word started with an unquoted `/` (e.g. `/s`), indicating that it's likely a switch
+	pub fn is_win_switch(&self) -> bool {
+		self.switch
+	}
+}
 
-		// Consume multiple separators
-		while endpos < self.0.len() && self.0[endpos] == b' ' {
-			self.0[endpos] = 0;
-			endpos += 1;
-		}
-		
-		let ret = &split_off_front_inplace_mut(&mut self.0, endpos)[..outpos];
-		Some( T::from_bytes(ret).expect("POSIX Word spliting caused UTF-8 inconsistency") )
+impl<'a, T: ?Sized + ByteStringSlice + 'a> Win32ShellWords<'a, T>
+{
+	/// Get the next word, along with its metadata
+	pub fn next_word(&mut self) -> Option<Word<'a, T>> {
+		// Separators have to be skipped first, so the check is against the start of the word
+		self.0.skip_separators();
+		let switch = self.0.remaining().first() == Some(&b'/');
+		self.0.next().map(|value| Word { value, switch })
+	}
+}
+impl<'a, T: ?Sized + ByteStringSlice + 'a> Iterator for Win32ShellWords<'a, T>
+{
+	type Item = &'a T;
+	fn next(&mut self) -> Option<&'a T> {
+		self.next_word().map(|w| w.value)
 	}
 }